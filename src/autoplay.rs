@@ -0,0 +1,188 @@
+use crate::error::{Error, Result};
+use crate::track::{SavedTrack, TrackSource};
+use crate::util::QueuedTrack;
+use rand::seq::SliceRandom;
+use serenity::async_trait;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::Context;
+use songbird::tracks::{PlayMode, TrackHandle};
+use songbird::{Event, EventContext, EventHandler};
+use yt_api::search::{ItemType, SearchList};
+
+/// How many of the latest plays autoplay avoids repeating.
+const RECENT_PLAYS: usize = 20;
+
+/// How many picks to try before giving up, when queuing them fails.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Keeps the music going after a track finishes, if it was the last one in
+/// the queue and autoplay is on in its guild.
+pub struct Autoplay {
+    pub ctx: Context,
+    pub guild_id: GuildId,
+    /// Who queued the track that ended. Autoplayed tracks are queued for them
+    /// too.
+    pub requester: UserId,
+    pub track: SavedTrack,
+}
+
+#[async_trait]
+impl EventHandler for Autoplay {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let tracks = match ctx {
+            EventContext::Track(tracks) => tracks,
+            _ => return None,
+        };
+        // Only tracks that play to the end count. /stop, /skip and leaving the
+        // call all stop the track instead, and should leave the call quiet.
+        let ended = tracks
+            .iter()
+            .find(|(state, _)| matches!(state.playing, PlayMode::End))
+            .map(|(_, handle)| (*handle).clone())?;
+
+        let ctx = self.ctx.clone();
+        let guild_id = self.guild_id;
+        let requester = self.requester;
+        let track = self.track.clone();
+        // Queuing can mean a download, which shouldn't hold up other events.
+        tokio::spawn(async move {
+            if let Err(e) = play_next(&ctx, guild_id, requester, &track, ended).await {
+                tracing::warn!(guild_id = %guild_id, error = %e, "autoplay failed");
+            }
+        });
+        None
+    }
+}
+
+async fn play_next(
+    ctx: &Context,
+    guild_id: GuildId,
+    requester: UserId,
+    previous: &SavedTrack,
+    ended: TrackHandle,
+) -> Result<()> {
+    if !crate::settings::guild_settings(guild_id).await?.autoplay {
+        return Ok(());
+    }
+
+    let handler_lock = match crate::util::current_call(ctx, guild_id).await {
+        Ok(handler_lock) => handler_lock,
+        // Left the call in the meantime.
+        Err(Error::NotConnected) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    {
+        let handler = crate::util::lock_call(&handler_lock).await?;
+        if handler
+            .queue()
+            .current_queue()
+            .iter()
+            .any(|track| track.uuid() != ended.uuid())
+        {
+            return Ok(());
+        }
+    }
+
+    let recent: Vec<TrackSource> = crate::history::list(guild_id, None)
+        .await?
+        .into_iter()
+        .take(RECENT_PLAYS)
+        .map(|entry| entry.track.source)
+        .collect();
+
+    let mut candidates = related(previous, &recent).await;
+    candidates.extend(random_picks(guild_id, requester, &recent).await?);
+
+    let mut last_error = Error::NoResults;
+    for candidate in candidates.iter().take(MAX_ATTEMPTS) {
+        // Wait for the download, so that a pick that can't be fetched makes
+        // way for the next one.
+        let fetched = match crate::util::queue_source(
+            ctx, guild_id, requester, candidate, None, None,
+        )
+        .await
+        {
+            Ok(QueuedTrack {
+                status: Some(mut status),
+                ..
+            }) => crate::prefetch::wait_until_fetched(&mut status)
+                .await
+                .map(|_| ())
+                .map_err(Error::DownloadFailed),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                tracing::info!(
+                    guild_id = %guild_id,
+                    source = ?candidate,
+                    error = %e,
+                    "autoplay pick could not be queued"
+                );
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Videos YouTube finds for the title of `previous`, if it was a YouTube
+/// track, leaving out anything played recently.
+async fn related(previous: &SavedTrack, recent: &[TrackSource]) -> Vec<TrackSource> {
+    if !matches!(previous.source, TrackSource::YouTube { .. })
+        || !crate::selfcheck::is_enabled(crate::selfcheck::Feature::Search)
+    {
+        return Vec::new();
+    }
+
+    let result = match SearchList::new(crate::util::YOUTUBE_API_KEY.clone())
+        .q(&previous.title)
+        .item_type(ItemType::Video)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::warn!(error = %e, "autoplay search failed");
+            return Vec::new();
+        }
+    };
+
+    result
+        .items
+        .into_iter()
+        .filter_map(|video| video.id.video_id)
+        .map(|id| TrackSource::YouTube { id })
+        .filter(|source| *source != previous.source && !recent.contains(source))
+        .collect()
+}
+
+/// Tracks from the history of `guild_id` and the favourites of `requester`,
+/// in a random order and leaving out anything played recently.
+async fn random_picks(
+    guild_id: GuildId,
+    requester: UserId,
+    recent: &[TrackSource],
+) -> Result<Vec<TrackSource>> {
+    let mut picks: Vec<TrackSource> = Vec::new();
+    let history = crate::history::list(guild_id, None).await?;
+    let favourites = crate::favourites::list(requester).await?;
+    for track in history
+        .into_iter()
+        .map(|entry| entry.track)
+        .chain(favourites)
+    {
+        // A radio stream never ends, so autoplay would stop with it.
+        if matches!(track.source, TrackSource::Radio { .. })
+            || recent.contains(&track.source)
+            || picks.contains(&track.source)
+        {
+            continue;
+        }
+        picks.push(track.source);
+    }
+
+    picks.shuffle(&mut rand::thread_rng());
+    Ok(picks)
+}
@@ -0,0 +1,88 @@
+use crate::error::{Error, Result};
+use crate::util::stable_hash;
+use std::path::Path;
+
+/// Where downloaded and mixed audio is kept.
+pub const QUEUE_DIR: &str = "./queue";
+
+/// Path of the mix of the track cached as `cache_id`, made with the ambience
+/// identified by `rain_fingerprint` and the rest of the parameters described
+/// by `params`.
+///
+/// Everything is hashed into the name, so that changing anything that goes
+/// into a mix makes a new one instead of reusing a stale one. Mixes without
+/// rain have an empty fingerprint.
+pub fn mix_path(cache_id: &str, rain_fingerprint: &str, params: &str) -> String {
+    format!(
+        "{}/{}{:016x}.ogg",
+        QUEUE_DIR,
+        mix_prefix(cache_id, rain_fingerprint),
+        stable_hash(params)
+    )
+}
+
+fn source_prefix(cache_id: &str) -> String {
+    format!("mix_{:016x}_", stable_hash(cache_id))
+}
+
+fn mix_prefix(cache_id: &str, rain_fingerprint: &str) -> String {
+    format!(
+        "{}{:016x}_",
+        source_prefix(cache_id),
+        stable_hash(rain_fingerprint)
+    )
+}
+
+/// Identifies the contents of the file at `path` well enough to notice it
+/// being replaced, without reading all of it.
+pub fn file_fingerprint(path: &str) -> Result<String> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| Error::MixFailed(format!("failed to read {}: {}", path, e)))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs())
+        .unwrap_or_default();
+    Ok(format!("{}:{}:{}", path, metadata.len(), modified))
+}
+
+/// Deletes the mixes of the track cached as `cache_id` that were made with
+/// some other ambience than `rain_fingerprint`, as well as the `{id}.mp3` mix
+/// from before mixes were named by their parameters. The `norain_{id}.mp3`
+/// download from back then is still played from, so it stays.
+///
+/// Mixes with other effects or without rain are kept, since other guilds
+/// may still be using them.
+pub fn remove_stale_mixes(cache_id: &str, rain_fingerprint: &str) {
+    let entries = match std::fs::read_dir(QUEUE_DIR) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to list cached audio");
+            return;
+        }
+    };
+
+    let source = source_prefix(cache_id);
+    let current = mix_prefix(cache_id, rain_fingerprint);
+    let dry = mix_prefix(cache_id, "");
+    let legacy_mix = format!("{}.mp3", cache_id);
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stale = if name.starts_with(&source) {
+            !name.starts_with(&current) && !name.starts_with(&dry)
+        } else {
+            name == legacy_mix
+        };
+        if stale {
+            remove_file(&entry.path());
+        }
+    }
+}
+
+fn remove_file(path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => tracing::info!(path = %path.display(), "removed stale mix"),
+        Err(e) => tracing::warn!(path = %path.display(), error = %e, "failed to remove stale mix"),
+    }
+}
@@ -0,0 +1,46 @@
+use super::Command;
+use crate::error::Result;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+
+pub struct Autoplay;
+
+#[async_trait]
+impl Command for Autoplay {
+    fn name(&self) -> &'static str {
+        "autoplay"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Keeps playing related tracks once the queue runs out")
+            .create_option(|option| {
+                option
+                    .name("on")
+                    .description("Turns autoplay on/off")
+                    .kind(CommandOptionType::Boolean)
+                    .required(true)
+            })
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let on = crate::util::bool_option(interaction, "on")?;
+        let guild_id = crate::util::guild_id(interaction)?;
+
+        crate::settings::update_guild_settings(guild_id, |settings| settings.autoplay = on).await?;
+
+        crate::util::respond_to_interaction(
+            interaction,
+            &ctx.http,
+            false,
+            format!("User {} set autoplay to {}", interaction.user.tag(), on),
+        )
+        .await
+    }
+}
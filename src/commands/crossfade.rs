@@ -0,0 +1,64 @@
+use super::Command;
+use crate::error::{Error, Result};
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+
+pub struct Crossfade;
+
+#[async_trait]
+impl Command for Crossfade {
+    fn name(&self) -> &'static str {
+        "crossfade"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Sets how long tracks fade into each other")
+            .create_option(|option| {
+                option
+                    .name("seconds")
+                    .description("Length of the crossfade, 0 to turn it off")
+                    .kind(CommandOptionType::Integer)
+                    .min_int_value(0)
+                    .max_int_value(crate::crossfade::MAX_CROSSFADE_SECS)
+                    .required(true)
+            })
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let seconds = crate::util::optional_int_option(interaction, "seconds")?
+            .ok_or(Error::MissingOption("seconds"))?;
+        let guild_id = crate::util::guild_id(interaction)?;
+        let seconds = u64::try_from(seconds)
+            .ok()
+            .filter(|seconds| *seconds <= crate::crossfade::MAX_CROSSFADE_SECS)
+            .ok_or(Error::InvalidOption("seconds"))?;
+
+        crate::settings::update_guild_settings(guild_id, |settings| {
+            settings.crossfade_secs = seconds
+        })
+        .await?;
+
+        crate::util::respond_to_interaction(
+            interaction,
+            &ctx.http,
+            false,
+            if seconds == 0 {
+                format!("User {} turned crossfade off", interaction.user.tag())
+            } else {
+                format!(
+                    "User {} set crossfade to {} seconds. It applies to tracks queued from now on",
+                    interaction.user.tag(),
+                    seconds
+                )
+            },
+        )
+        .await
+    }
+}
@@ -0,0 +1,93 @@
+use super::Command;
+use crate::effects::Speed;
+use crate::error::{Error, Result};
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+
+pub struct Effects;
+
+#[async_trait]
+impl Command for Effects {
+    fn name(&self) -> &'static str {
+        "effects"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Shows or changes the effects applied to the music")
+            .create_option(|option| {
+                option
+                    .name("muffled")
+                    .description("Muffles the music, like hearing it through a wall")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("reverb")
+                    .description("Adds reverb to the music")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("speed")
+                    .description("Changes the speed and pitch of the music")
+                    .kind(CommandOptionType::String)
+                    .add_string_choice("Normal", "normal")
+                    .add_string_choice("Slowed", "slowed")
+                    .add_string_choice("Nightcore", "nightcore")
+                    .required(false)
+            })
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let muffled = crate::util::optional_bool_option(interaction, "muffled")?;
+        let reverb = crate::util::optional_bool_option(interaction, "reverb")?;
+        let speed = crate::util::optional_string_option(interaction, "speed")?
+            .map(|speed| Speed::parse(speed).ok_or(Error::InvalidOption("speed")))
+            .transpose()?;
+
+        let changed = muffled.is_some() || reverb.is_some() || speed.is_some();
+        let effects = if changed {
+            crate::settings::update_guild_settings(guild_id, |settings| {
+                if let Some(muffled) = muffled {
+                    settings.effects.muffled = muffled;
+                }
+                if let Some(reverb) = reverb {
+                    settings.effects.reverb = reverb;
+                }
+                if let Some(speed) = speed {
+                    settings.effects.speed = speed;
+                }
+                settings.effects.clone()
+            })
+            .await?
+        } else {
+            crate::settings::guild_settings(guild_id).await?.effects
+        };
+
+        crate::util::respond_to_interaction(
+            interaction,
+            &ctx.http,
+            !changed,
+            if changed {
+                format!(
+                    "User {} set the effects to {}. They apply to tracks queued from now on",
+                    interaction.user.tag(),
+                    effects.describe()
+                )
+            } else {
+                format!("Effects: {}", effects.describe())
+            },
+        )
+        .await
+    }
+}
@@ -0,0 +1,135 @@
+use super::Command;
+use crate::error::{Error, Result};
+use crate::track::SavedTrack;
+use rand::seq::SliceRandom;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+use std::time::Duration;
+
+/// How many favourites `/fav list` shows.
+const SHOWN_FAVOURITES: usize = 25;
+
+pub struct Fav;
+
+#[async_trait]
+impl Command for Fav {
+    fn name(&self) -> &'static str {
+        "fav"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Keeps your favourite tracks, in every server")
+            .create_option(|option| {
+                option
+                    .name("add")
+                    .description("Adds the track that is playing to your favourites")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("list")
+                    .description("Lists your favourites")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("play")
+                    .description("Queues all of your favourites")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("shuffle")
+                            .description("Queue the tracks in a random order")
+                            .kind(CommandOptionType::Boolean)
+                            .required(false)
+                    })
+            })
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        Some(Duration::from_secs(3))
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        match crate::util::subcommand(interaction)? {
+            "add" => {
+                let guild_id = crate::util::guild_id(interaction)?;
+                let track = crate::util::current_track(ctx, guild_id).await?;
+                let metadata = crate::track::metadata_of(&track)
+                    .await
+                    .ok_or(Error::NothingPlaying)?;
+
+                let added =
+                    crate::favourites::add(interaction.user.id, SavedTrack::from(&*metadata))
+                        .await?;
+                let message = if added {
+                    format!("Added {} to your favourites", metadata.title)
+                } else {
+                    format!("{} is already one of your favourites", metadata.title)
+                };
+                crate::util::edit_response(interaction, &ctx.http, message).await
+            }
+            "list" => {
+                let favourites = crate::favourites::list(interaction.user.id).await?;
+                if favourites.is_empty() {
+                    return Err(Error::NoFavourites);
+                }
+
+                let mut description = favourites
+                    .iter()
+                    .take(SHOWN_FAVOURITES)
+                    .enumerate()
+                    .map(|(i, track)| format!("{}. {}", i + 1, track.title))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if favourites.len() > SHOWN_FAVOURITES {
+                    description.push_str(&format!(
+                        "\n...and {} more",
+                        favourites.len() - SHOWN_FAVOURITES
+                    ));
+                }
+
+                interaction
+                    .edit_original_interaction_response(&ctx.http, |edit| {
+                        edit.content("").embed(|embed| {
+                            embed
+                                .author(|author| {
+                                    author
+                                        .name(format!("Favourites of {}", interaction.user.tag()))
+                                        .icon_url(interaction.user.face())
+                                })
+                                .description(description)
+                        })
+                    })
+                    .await?;
+                Ok(())
+            }
+            "play" => {
+                crate::util::guild_id(interaction)?;
+                let shuffle =
+                    crate::util::optional_bool_option(interaction, "shuffle")?.unwrap_or(false);
+
+                let mut favourites = crate::favourites::list(interaction.user.id).await?;
+                if favourites.is_empty() {
+                    return Err(Error::NoFavourites);
+                }
+                if shuffle {
+                    favourites.shuffle(&mut rand::thread_rng());
+                }
+                crate::util::play_saved(ctx, interaction, "your favourites", &favourites).await
+            }
+            _ => Err(Error::UnknownCommand),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::Command;
+use crate::error::{Error, Result};
+use crate::history::HistoryEntry;
+use serenity::async_trait;
+use serenity::builder::{CreateApplicationCommand, CreateComponents, CreateEmbed};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::id::UserId;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
+use serenity::prelude::*;
+
+/// Plays shown per page, each with a button to queue it again.
+const PAGE_SIZE: usize = 5;
+
+pub struct History;
+
+#[async_trait]
+impl Command for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Shows what has been played in this server")
+            .create_option(|option| {
+                option
+                    .name("user")
+                    .description("Only show tracks this user queued")
+                    .kind(CommandOptionType::User)
+                    .required(false)
+            })
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let filter = crate::util::optional_user_option(interaction, "user")?.map(|user| user.id);
+
+        let entries = crate::history::list(guild_id, filter).await?;
+        let (embed, components) = history_page(&entries, filter, 0);
+        interaction
+            .create_interaction_response(&ctx.http, |create| {
+                create.interaction_response_data(|data| {
+                    data.set_embed(embed).set_components(components)
+                })
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Handles the buttons under a `/history` page.
+///
+/// Their custom ids are `history:page:<user id or all>:<page>` to turn the
+/// page and `history:queue:<entry id>` to queue a play again.
+pub async fn handle_component(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    deferred: &mut bool,
+) -> Result<()> {
+    let guild_id = component.guild_id.ok_or(Error::NotInGuild)?;
+    let parts: Vec<&str> = component.data.custom_id.split(':').collect();
+
+    match parts.as_slice() {
+        ["history", "page", filter, page] => {
+            let filter = match *filter {
+                "all" => None,
+                user_id => Some(UserId(user_id.parse().map_err(|_| Error::UnknownCommand)?)),
+            };
+            let page = page.parse().map_err(|_| Error::UnknownCommand)?;
+
+            let entries = crate::history::list(guild_id, filter).await?;
+            let (embed, components) = history_page(&entries, filter, page);
+            component
+                .create_interaction_response(&ctx.http, |create| {
+                    create
+                        .kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|data| {
+                            data.set_embed(embed).set_components(components)
+                        })
+                })
+                .await?;
+            Ok(())
+        }
+        ["history", "queue", id] => {
+            let id: u64 = id.parse().map_err(|_| Error::UnknownCommand)?;
+            let entry = crate::history::list(guild_id, None)
+                .await?
+                .into_iter()
+                .find(|entry| entry.id == id)
+                .ok_or_else(|| {
+                    Error::TrackRejected("That play is no longer in the history".to_string())
+                })?;
+
+            component
+                .create_interaction_response(&ctx.http, |create| {
+                    create.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                })
+                .await?;
+            *deferred = true;
+
+            let queued = crate::util::queue_source(
+                ctx,
+                guild_id,
+                component.user.id,
+                &entry.track.source,
+                None,
+                Some(component.channel_id),
+            )
+            .await?;
+            component
+                .edit_original_interaction_response(&ctx.http, |edit| {
+                    edit.embed(|embed| crate::util::queued_embed(embed, &component.user, &queued))
+                })
+                .await?;
+            crate::util::report_progress(
+                ctx,
+                guild_id,
+                component.token.clone(),
+                component.user.clone(),
+                queued,
+            );
+            Ok(())
+        }
+        _ => Err(Error::UnknownCommand),
+    }
+}
+
+/// Builds page `page` of `entries`, along with its buttons.
+fn history_page(
+    entries: &[HistoryEntry],
+    filter: Option<UserId>,
+    page: usize,
+) -> (CreateEmbed, CreateComponents) {
+    let pages = entries.len().div_ceil(PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+    let shown: Vec<&HistoryEntry> = entries
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .collect();
+
+    let mut embed = CreateEmbed::default();
+    embed.title("Play history");
+    let mut description = match filter {
+        Some(user_id) => format!("Tracks queued by {}\n\n", user_id.mention()),
+        None => String::new(),
+    };
+    if shown.is_empty() {
+        description.push_str("Nothing has been played yet");
+    }
+    for (i, entry) in shown.iter().enumerate() {
+        description.push_str(&format!(
+            "{}. {} - {} <t:{}:R>{}\n",
+            i + 1,
+            entry.track.title,
+            entry.requester.mention(),
+            entry.started_at,
+            if entry.rain_enabled { " with rain" } else { "" }
+        ));
+    }
+    embed
+        .description(description)
+        .footer(|footer| footer.text(format!("Page {} of {}", page + 1, pages)));
+
+    let mut components = CreateComponents::default();
+    if !shown.is_empty() {
+        components.create_action_row(|row| {
+            for (i, entry) in shown.iter().enumerate() {
+                row.create_button(|button| {
+                    button
+                        .custom_id(format!("history:queue:{}", entry.id))
+                        .label(format!("Queue {}", i + 1))
+                        .style(ButtonStyle::Secondary)
+                });
+            }
+            row
+        });
+    }
+    let filter = filter
+        .map(|user_id| user_id.to_string())
+        .unwrap_or_else(|| "all".to_string());
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!(
+                    "history:page:{}:{}",
+                    filter,
+                    page.saturating_sub(1)
+                ))
+                .label("Previous")
+                .style(ButtonStyle::Primary)
+                .disabled(page == 0)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!("history:page:{}:{}", filter, page + 1))
+                .label("Next")
+                .style(ButtonStyle::Primary)
+                .disabled(page + 1 >= pages)
+        })
+    });
+
+    (embed, components)
+}
@@ -1,100 +1,41 @@
+use super::Command;
+use crate::error::{Error, Result};
+use serenity::async_trait;
 use serenity::{
-    builder::CreateApplicationCommand,
-    client::Context,
-    model::{
-        application::interaction::InteractionResponseType,
-        prelude::interaction::application_command::ApplicationCommandInteraction,
-    },
+    builder::CreateApplicationCommand, client::Context,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
     prelude::Mentionable,
 };
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    let guild_id = match interaction.guild_id {
-        Some(id) => id,
-        None => {
-            match interaction
-                .create_interaction_response(&ctx.http, |r| {
-                    r.kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|m| {
-                            m.content("This command can only be used in a server")
-                        })
-                })
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    eprintln!("Cannot respond to slash command: {}", e);
-                    return;
-                }
-            }
-        }
-    };
-    let http = ctx.http.clone();
-    let guild = match ctx.cache.guild(guild_id) {
-        Some(guild) => guild,
-        None => {
-            crate::util::respond_to_interaction(
-                interaction,
-                &http,
-                true,
-                "Internal Error: Failed to get guild from cache",
-            )
-            .await;
-            return;
-        }
-    };
+pub struct Join;
 
-    let channel_id = guild
-        .voice_states
-        .get(&interaction.user.id)
-        .and_then(|voice_state| voice_state.channel_id);
+#[async_trait]
+impl Command for Join {
+    fn name(&self) -> &'static str {
+        "join"
+    }
 
-    let connect_to = match channel_id {
-        Some(channel) => channel,
-        None => {
-            crate::util::respond_to_interaction(
-                interaction,
-                &http,
-                true,
-                "You must be in a voice channel to use this command",
-            )
-            .await;
-            return;
-        }
-    };
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command.description("Has the bot join your voice channel")
+    }
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let connect_to = crate::util::user_voice_channel(ctx, guild_id, interaction.user.id)?;
 
-    let (_, success) = manager.join(guild_id, connect_to).await;
+        let manager = crate::util::songbird_manager(ctx).await;
+        let (_, success) = manager.join(guild_id, connect_to).await;
+        success.map_err(Error::JoinFailed)?;
 
-    match success {
-        Ok(_) => {
-            crate::util::respond_to_interaction(
-                interaction,
-                &http,
-                false,
-                format!("Joined {}", connect_to.mention()),
-            )
-            .await;
-        }
-        Err(e) => {
-            eprintln!("Failed to join voice channel: {}", e);
-            crate::util::respond_to_interaction(
-                interaction,
-                &http,
-                true,
-                "Failed to join voice channel",
-            )
-            .await;
-        }
+        crate::util::respond_to_interaction(
+            interaction,
+            &ctx.http,
+            false,
+            format!("Joined {}", connect_to.mention()),
+        )
+        .await
     }
 }
-
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
-        .name("join")
-        .description("Has the bot join your voice channel")
-}
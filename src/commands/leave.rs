@@ -1,55 +1,38 @@
+use super::Command;
+use crate::error::{Error, Result};
+use serenity::async_trait;
 use serenity::{
-    builder::CreateApplicationCommand,
-    client::Context,
-    model::{
-        application::interaction::InteractionResponseType,
-        prelude::interaction::application_command::ApplicationCommandInteraction,
-    },
+    builder::CreateApplicationCommand, client::Context,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
 };
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    let guild_id = match interaction.guild_id {
-        Some(id) => id,
-        None => {
-            match interaction
-                .create_interaction_response(&ctx.http, |r| {
-                    r.kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|m| {
-                            m.content("This command can only be used in a server")
-                        })
-                })
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    eprintln!("Cannot respond to slash command: {}", e);
-                    return;
-                }
-            }
-        }
-    };
-    let http = ctx.http.clone();
+pub struct Leave;
+
+#[async_trait]
+impl Command for Leave {
+    fn name(&self) -> &'static str {
+        "leave"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command.description("Has the bot leave the voice channel")
+    }
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-    let has_handler = manager.get(guild_id).is_some();
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+
+        let manager = crate::util::songbird_manager(ctx).await;
+        if manager.get(guild_id).is_none() {
+            return Err(Error::NotConnected);
+        }
 
-    if has_handler {
         if let Err(e) = manager.remove(guild_id).await {
-            eprintln!("Error removing guild from channel list: {:?}", e);
+            tracing::warn!(guild_id = %guild_id, error = %e, "error removing guild from channel list");
         }
 
-        crate::util::respond_to_interaction(interaction, &http, false, "Left channel").await;
-    } else {
-        crate::util::respond_to_interaction(interaction, &http, true, "Not in a channel to leave")
-            .await;
+        crate::util::respond_to_interaction(interaction, &ctx.http, false, "Left channel").await
     }
 }
-
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
-        .name("leave")
-        .description("Has the bot leave the voice channel")
-}
@@ -0,0 +1,97 @@
+use super::Command;
+use crate::error::Result;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::permissions::Permissions;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+use std::time::Duration;
+
+pub struct Limits;
+
+#[async_trait]
+impl Command for Limits {
+    fn name(&self) -> &'static str {
+        "limits"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Shows or changes the limits on what can be queued in this server")
+            .create_option(|option| {
+                option
+                    .name("max_minutes")
+                    .description("Longest track that can be queued, in minutes")
+                    .kind(CommandOptionType::Integer)
+                    .min_int_value(1)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("max_megabytes")
+                    .description("Largest download that can be queued, in megabytes")
+                    .kind(CommandOptionType::Integer)
+                    .min_int_value(1)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("allow_live")
+                    .description("Whether live streams can be queued")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            })
+    }
+
+    fn permissions(&self) -> Permissions {
+        Permissions::MANAGE_GUILD
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let max_minutes = crate::util::optional_int_option(interaction, "max_minutes")?;
+        let max_megabytes = crate::util::optional_int_option(interaction, "max_megabytes")?;
+        let allow_live = crate::util::optional_bool_option(interaction, "allow_live")?;
+
+        let changed = max_minutes.is_some() || max_megabytes.is_some() || allow_live.is_some();
+        let settings = if changed {
+            crate::settings::update_guild_settings(guild_id, |settings| {
+                if let Some(max_minutes) = max_minutes {
+                    settings.max_duration_secs = max_minutes.max(1) as u64 * 60;
+                }
+                if let Some(max_megabytes) = max_megabytes {
+                    settings.max_filesize_mb = max_megabytes.max(1) as u64;
+                }
+                if let Some(allow_live) = allow_live {
+                    settings.allow_live = allow_live;
+                }
+                settings.clone()
+            })
+            .await?
+        } else {
+            crate::settings::guild_settings(guild_id).await?
+        };
+
+        crate::util::respond_to_interaction(
+            interaction,
+            &ctx.http,
+            !changed,
+            format!(
+                "{}Longest track: {}\nLargest download: {} MB\nLive streams: {}",
+                if changed { "Limits updated\n" } else { "" },
+                crate::track::format_duration(Duration::from_secs(settings.max_duration_secs)),
+                settings.max_filesize_mb,
+                if settings.allow_live {
+                    "allowed"
+                } else {
+                    "not allowed"
+                }
+            ),
+        )
+        .await
+    }
+}
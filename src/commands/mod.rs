@@ -1,10 +1,368 @@
+use crate::error::{Error, Result};
+use crate::store::Store;
+use lazy_static::lazy_static;
+use serenity::async_trait;
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommands};
+use serenity::model::application::command::Command as ApplicationCommand;
+use serenity::model::id::{ApplicationId, GuildId, UserId};
+use serenity::model::permissions::Permissions;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
+use serenity::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+pub mod autoplay;
+pub mod crossfade;
+pub mod effects;
+pub mod fav;
+pub mod history;
 pub mod join;
 pub mod leave;
+pub mod limits;
 pub mod mute;
+pub mod nowplaying;
 pub mod ping;
+pub mod playlist;
 pub mod queue;
+pub mod radio;
 pub mod search;
 pub mod setrain;
 pub mod skip;
 pub mod stop;
 pub mod unmute;
+
+/// Where the hash of the last globally registered command schema is kept.
+const SCHEMA_HASH_PATH: &str = "./data/command_schema.hash";
+
+lazy_static! {
+    /// Guilds whose own copies of the commands have been removed, by id.
+    static ref CLEARED_GUILDS: Store<HashSet<u64>> = Store::open("cleared_guild_commands");
+    pub static ref REGISTRY: Registry = Registry::new(vec![
+        Box::new(join::Join),
+        Box::new(leave::Leave),
+        Box::new(mute::Mute),
+        Box::new(ping::Ping),
+        Box::new(queue::Queue),
+        Box::new(skip::Skip),
+        Box::new(stop::Stop),
+        Box::new(unmute::Unmute),
+        Box::new(search::Search),
+        Box::new(setrain::SetRain),
+        Box::new(limits::Limits),
+        Box::new(radio::Radio),
+        Box::new(nowplaying::NowPlaying),
+        Box::new(playlist::Playlist),
+        Box::new(fav::Fav),
+        Box::new(history::History),
+        Box::new(autoplay::Autoplay),
+        Box::new(crossfade::Crossfade),
+        Box::new(effects::Effects),
+    ]);
+}
+
+/// A slash command that can be registered with Discord and dispatched to.
+#[async_trait]
+pub trait Command: Send + Sync {
+    /// The name the command is registered and dispatched under.
+    fn name(&self) -> &'static str;
+
+    /// Fills in everything but the name, which the registry sets from `name`.
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand;
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()>;
+
+    /// Permissions a member needs to use the command.
+    fn permissions(&self) -> Permissions {
+        Permissions::empty()
+    }
+
+    /// How long a user has to wait between uses of the command.
+    fn cooldown(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether the command may take longer than Discord's 3 second deadline
+    /// to respond. Deferred commands are acknowledged before they run and
+    /// must edit the original response instead of creating one.
+    fn defer(&self) -> bool {
+        false
+    }
+}
+
+pub struct Registry {
+    commands: Vec<Box<dyn Command>>,
+    last_used: Mutex<HashMap<(&'static str, UserId), Instant>>,
+}
+
+impl Registry {
+    pub fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        Self {
+            commands,
+            last_used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Command> {
+        self.commands
+            .iter()
+            .find(|command| command.name() == name)
+            .map(|command| command.as_ref())
+    }
+
+    pub fn create_commands<'a>(
+        &self,
+        commands: &'a mut CreateApplicationCommands,
+    ) -> &'a mut CreateApplicationCommands {
+        for command in &self.commands {
+            commands.create_application_command(|builder| {
+                command.register(builder).name(command.name());
+                let permissions = command.permissions();
+                if !permissions.is_empty() {
+                    builder.default_member_permissions(permissions);
+                }
+                builder
+            });
+        }
+        commands
+    }
+
+    pub async fn dispatch(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) {
+        let mut deferred = false;
+        let result = match self.get(&interaction.data.name) {
+            Some(command) => {
+                self.run_command(command, ctx, interaction, &mut deferred)
+                    .await
+            }
+            None => Err(Error::UnknownCommand),
+        };
+
+        if let Err(e) = result {
+            crate::util::report_error(ctx, interaction, deferred, &e).await;
+        }
+    }
+
+    async fn run_command(
+        &self,
+        command: &dyn Command,
+        ctx: &Context,
+        interaction: &ApplicationCommandInteraction,
+        deferred: &mut bool,
+    ) -> Result<()> {
+        let required = command.permissions();
+        if !required.is_empty() {
+            let granted = interaction
+                .member
+                .as_ref()
+                .and_then(|member| member.permissions)
+                .unwrap_or_else(Permissions::empty);
+            if !granted.contains(required) {
+                return Err(Error::MissingPermissions);
+            }
+        }
+
+        if let Some(cooldown) = command.cooldown() {
+            let mut last_used = self.last_used.lock().await;
+            let key = (command.name(), interaction.user.id);
+            if let Some(used_at) = last_used.get(&key) {
+                let elapsed = used_at.elapsed();
+                if elapsed < cooldown {
+                    return Err(Error::Cooldown(cooldown - elapsed));
+                }
+            }
+            last_used.insert(key, Instant::now());
+        }
+
+        if command.defer() {
+            crate::util::defer_interaction(interaction, &ctx.http).await?;
+            *deferred = true;
+        }
+
+        command.run(ctx, interaction).await
+    }
+}
+
+/// Routes a button press to the command that sent the button, going by the
+/// first part of its custom id.
+pub async fn dispatch_component(ctx: &Context, component: &MessageComponentInteraction) {
+    let mut deferred = false;
+    let result = match component.data.custom_id.split(':').next() {
+        Some("history") => history::handle_component(ctx, component, &mut deferred).await,
+        _ => Err(Error::UnknownCommand),
+    };
+
+    if let Err(e) = result {
+        crate::util::report_component_error(ctx, component, deferred, &e).await;
+    }
+}
+
+/// Hashes the command schema, along with the application it's registered
+/// for, in case the data directory is reused for another bot.
+fn schema_hash(application_id: ApplicationId) -> String {
+    let mut commands = CreateApplicationCommands::default();
+    REGISTRY.create_commands(&mut commands);
+
+    let schema = serde_json::to_string(&commands.0).unwrap_or_default();
+    let hash = crate::util::stable_hash(&format!("{}:{}", application_id, schema));
+    format!("{:016x}", hash)
+}
+
+/// Whether the global commands Discord has are the ones in the registry, by
+/// name. Catches commands deleted on Discord's side, which the schema hash
+/// alone can't.
+async fn global_commands_match(ctx: &Context) -> bool {
+    match ApplicationCommand::get_global_application_commands(&ctx.http).await {
+        Ok(registered) => {
+            let mut registered: Vec<&str> = registered
+                .iter()
+                .map(|command| command.name.as_str())
+                .collect();
+            let mut expected: Vec<&str> = REGISTRY.commands.iter().map(|c| c.name()).collect();
+            registered.sort_unstable();
+            expected.sort_unstable();
+            registered == expected
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to fetch global slash commands");
+            false
+        }
+    }
+}
+
+/// Registers the slash commands with Discord.
+///
+/// If `DEV_GUILD_ID` is set, the commands are registered to that guild only,
+/// which Discord applies instantly, and are re-synced on every start. Global
+/// commands are left alone, as the token may be shared with production.
+/// Otherwise they are registered globally, and only when the command schema
+/// differs from the one registered last time or Discord's copy is missing
+/// commands.
+pub async fn sync(ctx: &Context, application_id: ApplicationId) {
+    if let Ok(dev_guild) = std::env::var("DEV_GUILD_ID") {
+        let guild_id = match dev_guild.parse::<u64>() {
+            Ok(id) => GuildId(id),
+            Err(e) => {
+                tracing::error!(dev_guild, error = %e, "invalid DEV_GUILD_ID");
+                return;
+            }
+        };
+
+        match guild_id
+            .set_application_commands(&ctx.http, |commands| REGISTRY.create_commands(commands))
+            .await
+        {
+            Ok(_) => {
+                tracing::info!(guild_id = %guild_id, "registered slash commands for dev guild")
+            }
+            Err(e) => tracing::error!(
+                guild_id = %guild_id,
+                error = %e,
+                "failed to register slash commands for dev guild"
+            ),
+        }
+        return;
+    }
+
+    let hash = schema_hash(application_id);
+    if let Ok(previous) = std::fs::read_to_string(SCHEMA_HASH_PATH) {
+        if previous.trim() == hash && global_commands_match(ctx).await {
+            tracing::info!("slash commands unchanged, skipping registration");
+            return;
+        }
+    }
+
+    match ApplicationCommand::set_global_application_commands(&ctx.http, |commands| {
+        REGISTRY.create_commands(commands)
+    })
+    .await
+    {
+        Ok(_) => tracing::info!("registered global slash commands"),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to register global slash commands");
+            return;
+        }
+    }
+
+    let hash_path = std::path::Path::new(SCHEMA_HASH_PATH);
+    if let Some(parent) = hash_path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            tracing::warn!(path = %parent.display(), error = %e, "failed to create data directory");
+            return;
+        }
+    }
+    if let Err(e) = std::fs::write(hash_path, hash) {
+        tracing::warn!(error = %e, "failed to write command schema hash");
+    }
+}
+
+/// Removes the commands registered to `guild_id` from back when every guild
+/// got its own copy, which would otherwise show up next to the global ones.
+///
+/// Done once per guild, remembered in the data directory. Skipped in dev
+/// guild mode, where guild commands are the point.
+pub async fn clear_guild_commands(ctx: &Context, guild_id: GuildId) {
+    if std::env::var("DEV_GUILD_ID").is_ok() {
+        return;
+    }
+    match CLEARED_GUILDS
+        .read(|cleared| cleared.contains(&guild_id.0))
+        .await
+    {
+        Ok(false) => {}
+        Ok(true) => return,
+        Err(e) => {
+            tracing::warn!(guild_id = %guild_id, error = %e, "failed to check for guild slash commands");
+            return;
+        }
+    }
+
+    if let Err(e) = guild_id
+        .set_application_commands(&ctx.http, |commands| commands)
+        .await
+    {
+        tracing::warn!(guild_id = %guild_id, error = %e, "failed to remove guild slash commands");
+        return;
+    }
+    tracing::info!(guild_id = %guild_id, "removed guild slash commands in favour of global ones");
+    if let Err(e) = CLEARED_GUILDS
+        .update(|cleared| cleared.insert(guild_id.0))
+        .await
+    {
+        tracing::warn!(guild_id = %guild_id, error = %e, "failed to record removed guild slash commands");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_registered_command_is_dispatchable() {
+        let mut commands = CreateApplicationCommands::default();
+        REGISTRY.create_commands(&mut commands);
+
+        assert_eq!(commands.0.len(), REGISTRY.commands.len());
+        for command in &commands.0 {
+            let name = command
+                .get("name")
+                .and_then(|name| name.as_str())
+                .expect("registered command has no name");
+            assert!(
+                REGISTRY.get(name).is_some(),
+                "/{} is registered but not dispatchable",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn command_names_are_unique() {
+        let mut names: Vec<_> = REGISTRY.commands.iter().map(|c| c.name()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), REGISTRY.commands.len());
+    }
+}
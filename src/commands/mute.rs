@@ -1,80 +1,43 @@
+use super::Command;
+use crate::error::Result;
+use serenity::async_trait;
 use serenity::{
-    builder::CreateApplicationCommand,
-    client::Context,
-    model::{
-        application::interaction::InteractionResponseType,
-        prelude::interaction::application_command::ApplicationCommandInteraction,
-    },
+    builder::CreateApplicationCommand, client::Context,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
 };
-use tokio::time::timeout;
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    let guild_id = match interaction.guild_id {
-        Some(id) => id,
-        None => {
-            match interaction
-                .create_interaction_response(&ctx.http, |r| {
-                    r.kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|m| {
-                            m.content("This command can only be used in a server")
-                        })
-                })
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    eprintln!("Cannot respond to slash command: {}", e);
-                    return;
-                }
-            }
-        }
-    };
-    let http = ctx.http.clone();
+pub struct Mute;
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+#[async_trait]
+impl Command for Mute {
+    fn name(&self) -> &'static str {
+        "mute"
+    }
 
-    let handler_lock = match manager.get(guild_id) {
-        Some(handler) => handler,
-        None => {
-            crate::util::respond_to_interaction(interaction, &http, true, "Not in a voice channel")
-                .await;
-            return;
-        }
-    };
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Has the bot mute itself. The song will continue playing if there is a song currently playing.")
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let handler_lock = crate::util::current_call(ctx, guild_id).await?;
+        let mut handler = crate::util::lock_call(&handler_lock).await?;
 
-    let mut handler = match timeout(std::time::Duration::from_secs(5), handler_lock.lock()).await {
-        Ok(handler) => handler,
-        Err(e) => {
-            eprintln!("Failed to lock handler with error {}", e);
-            crate::util::respond_to_interaction(
+        if handler.is_mute() {
+            return crate::util::respond_to_interaction(
                 interaction,
                 &ctx.http,
                 true,
-                "There was an error. Please try again later.",
+                "Already muted",
             )
             .await;
-
-            return;
         }
-    };
 
-    if handler.is_mute() {
-        crate::util::respond_to_interaction(interaction, &http, true, "Already muted").await;
-    } else {
-        if let Err(e) = handler.mute(true).await {
-            eprintln!("Failed to mute: {:?}", e);
-            crate::util::respond_to_interaction(interaction, &http, true, "Failed to mute").await;
-        } else {
-            crate::util::respond_to_interaction(interaction, &http, false, "Now muted").await;
-        }
+        handler.mute(true).await?;
+        crate::util::respond_to_interaction(interaction, &ctx.http, false, "Now muted").await
     }
 }
-
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
-        .name("mute")
-        .description("Has the bot mute itself. The song will continue playing if there is a song currently playing.")
-}
@@ -0,0 +1,86 @@
+use super::Command;
+use crate::error::{Error, Result};
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+
+pub struct NowPlaying;
+
+#[async_trait]
+impl Command for NowPlaying {
+    fn name(&self) -> &'static str {
+        "nowplaying"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command.description("Shows what is currently playing")
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let handler_lock = crate::util::current_call(ctx, guild_id).await?;
+        let track = {
+            let handler = crate::util::lock_call(&handler_lock).await?;
+            handler.queue().current().ok_or(Error::NothingPlaying)?
+        };
+
+        let metadata = crate::track::metadata_of(&track)
+            .await
+            .ok_or(Error::NothingPlaying)?;
+        let position = track
+            .get_info()
+            .await
+            .map(|state| state.position)
+            .unwrap_or_default();
+        let stream_title = track
+            .typemap()
+            .read()
+            .await
+            .get::<crate::radio::StreamTitleKey>()
+            .cloned();
+
+        interaction
+            .create_interaction_response(&ctx.http, |create| {
+                create.interaction_response_data(|data| {
+                    data.embed(|embed| {
+                        embed.title(&metadata.title);
+                        if let Some(url) = &metadata.webpage_url {
+                            embed.url(url);
+                        }
+                        if let Some(channel) = metadata.channel_name() {
+                            embed.field("Channel", channel, true);
+                        }
+                        embed.field(
+                            "Position",
+                            match metadata.duration() {
+                                _ if metadata.is_live() => "Live".to_string(),
+                                Some(duration) => format!(
+                                    "{} / {}",
+                                    crate::track::format_duration(position),
+                                    crate::track::format_duration(duration)
+                                ),
+                                None => crate::track::format_duration(position),
+                            },
+                            true,
+                        );
+                        if let Some(chapter) = metadata.chapter_at(position) {
+                            embed.field("Chapter", &chapter.title, true);
+                        }
+                        if let Some(stream_title) = &stream_title {
+                            embed.field("Now on air", stream_title, false);
+                        }
+                        if let Some(thumbnail) = &metadata.thumbnail {
+                            embed.thumbnail(thumbnail);
+                        }
+                        embed
+                    })
+                })
+            })
+            .await?;
+        Ok(())
+    }
+}
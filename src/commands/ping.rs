@@ -1,24 +1,39 @@
+use super::Command;
+use crate::error::Result;
+use serenity::async_trait;
 use serenity::{
     builder::CreateApplicationCommand, client::Context,
     model::prelude::interaction::application_command::ApplicationCommandInteraction,
 };
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    crate::util::respond_to_interaction(
-        interaction,
-        &ctx.http,
-        true,
-        format!(
-            "Pong! Bot time is <t:{}:F>",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        ),
-    )
-    .await;
-}
+pub struct Ping;
+
+#[async_trait]
+impl Command for Ping {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command.description("A ping command")
+    }
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command.name("ping").description("A ping command")
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        crate::util::respond_to_interaction(
+            interaction,
+            &ctx.http,
+            true,
+            format!(
+                "Pong! Bot time is <t:{}:F>",
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            ),
+        )
+        .await
+    }
 }
@@ -0,0 +1,327 @@
+use super::Command;
+use crate::error::{Error, Result};
+use crate::track::{SavedTrack, TrackSource};
+use rand::seq::SliceRandom;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::id::GuildId;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+use std::time::Duration;
+
+/// How many tracks `/playlist show` lists.
+const SHOWN_TRACKS: usize = 25;
+
+pub struct Playlist;
+
+#[async_trait]
+impl Command for Playlist {
+    fn name(&self) -> &'static str {
+        "playlist"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Saves tracks to play again later")
+            .create_option(|option| {
+                option
+                    .name("create")
+                    .description("Creates an empty playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(name_option)
+            })
+            .create_option(|option| {
+                option
+                    .name("add")
+                    .description("Adds a track to a playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(name_option)
+                    .create_sub_option(|option| {
+                        option
+                            .name("track")
+                            .description(
+                                "YouTube URL, local:<path>, station or stream URL. Defaults to what is playing",
+                            )
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("remove")
+                    .description("Removes a track from a playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(name_option)
+                    .create_sub_option(|option| {
+                        option
+                            .name("position")
+                            .description("Position of the track, as listed by /playlist show")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("show")
+                    .description("Lists the tracks of a playlist, or every playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| name_option(option).required(false))
+            })
+            .create_option(|option| {
+                option
+                    .name("play")
+                    .description("Queues every track of a playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(name_option)
+                    .create_sub_option(|option| {
+                        option
+                            .name("shuffle")
+                            .description("Queue the tracks in a random order")
+                            .kind(CommandOptionType::Boolean)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("delete")
+                    .description("Deletes a playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(name_option)
+            })
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        Some(Duration::from_secs(3))
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+
+        match crate::util::subcommand(interaction)? {
+            "create" => {
+                let name = crate::util::string_option(interaction, "name")?;
+                crate::playlists::create(guild_id, name, interaction.user.id).await?;
+                crate::util::edit_response(
+                    interaction,
+                    &ctx.http,
+                    format!("Created playlist {}", name.trim()),
+                )
+                .await
+            }
+            "add" => {
+                let name = crate::util::string_option(interaction, "name")?;
+                check_owner(interaction, &crate::playlists::get(guild_id, name).await?)?;
+                let track = resolve_track(
+                    ctx,
+                    guild_id,
+                    crate::util::optional_string_option(interaction, "track")?,
+                )
+                .await?;
+
+                let title = track.title.clone();
+                let playlist = crate::playlists::update(guild_id, name, |playlist| {
+                    if playlist.tracks.len() >= crate::playlists::MAX_TRACKS {
+                        return Err(Error::TrackRejected(format!(
+                            "Playlists can hold at most {} tracks",
+                            crate::playlists::MAX_TRACKS
+                        )));
+                    }
+                    playlist.tracks.push(track);
+                    Ok(playlist.name.clone())
+                })
+                .await?;
+                crate::util::edit_response(
+                    interaction,
+                    &ctx.http,
+                    format!("Added {} to {}", title, playlist),
+                )
+                .await
+            }
+            "remove" => {
+                let name = crate::util::string_option(interaction, "name")?;
+                let position = crate::util::optional_int_option(interaction, "position")?
+                    .ok_or(Error::MissingOption("position"))?;
+                check_owner(interaction, &crate::playlists::get(guild_id, name).await?)?;
+
+                let (removed, playlist) = crate::playlists::update(guild_id, name, |playlist| {
+                    let index = usize::try_from(position - 1)
+                        .ok()
+                        .filter(|index| *index < playlist.tracks.len())
+                        .ok_or(Error::InvalidOption("position"))?;
+                    Ok((playlist.tracks.remove(index), playlist.name.clone()))
+                })
+                .await?;
+                crate::util::edit_response(
+                    interaction,
+                    &ctx.http,
+                    format!("Removed {} from {}", removed.title, playlist),
+                )
+                .await
+            }
+            "show" => match crate::util::optional_string_option(interaction, "name")? {
+                Some(name) => {
+                    let playlist = crate::playlists::get(guild_id, name).await?;
+                    show_playlist(ctx, interaction, &playlist).await
+                }
+                None => show_playlists(ctx, interaction, guild_id).await,
+            },
+            "play" => {
+                let name = crate::util::string_option(interaction, "name")?;
+                let shuffle =
+                    crate::util::optional_bool_option(interaction, "shuffle")?.unwrap_or(false);
+
+                let mut playlist = crate::playlists::get(guild_id, name).await?;
+                if playlist.tracks.is_empty() {
+                    return Err(Error::EmptyPlaylist(playlist.name));
+                }
+                if shuffle {
+                    playlist.tracks.shuffle(&mut rand::thread_rng());
+                }
+                crate::util::play_saved(ctx, interaction, &playlist.name, &playlist.tracks).await
+            }
+            "delete" => {
+                let name = crate::util::string_option(interaction, "name")?;
+                check_owner(interaction, &crate::playlists::get(guild_id, name).await?)?;
+                let playlist = crate::playlists::delete(guild_id, name).await?;
+                crate::util::edit_response(
+                    interaction,
+                    &ctx.http,
+                    format!("Deleted playlist {}", playlist.name),
+                )
+                .await
+            }
+            _ => Err(Error::UnknownCommand),
+        }
+    }
+}
+
+fn name_option(
+    option: &mut serenity::builder::CreateApplicationCommandOption,
+) -> &mut serenity::builder::CreateApplicationCommandOption {
+    option
+        .name("name")
+        .description("Name of the playlist")
+        .kind(CommandOptionType::String)
+        .required(true)
+}
+
+/// Only the creator of a playlist and members who can manage the server may
+/// change it.
+fn check_owner(
+    interaction: &ApplicationCommandInteraction,
+    playlist: &crate::playlists::Playlist,
+) -> Result<()> {
+    let manages_guild = interaction
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .map(|permissions| permissions.manage_guild())
+        .unwrap_or(false);
+    if playlist.owner == interaction.user.id || manages_guild {
+        Ok(())
+    } else {
+        Err(Error::MissingPermissions)
+    }
+}
+
+/// Works out what `/playlist add` should save: the track named by `input`, or
+/// the one playing if there is no input.
+async fn resolve_track(
+    ctx: &Context,
+    guild_id: GuildId,
+    input: Option<&str>,
+) -> Result<SavedTrack> {
+    let input = match input {
+        Some(input) => input,
+        None => {
+            let track = crate::util::current_track(ctx, guild_id).await?;
+            let metadata = crate::track::metadata_of(&track)
+                .await
+                .ok_or(Error::NothingPlaying)?;
+            return Ok(SavedTrack::from(&*metadata));
+        }
+    };
+
+    let source = TrackSource::parse(input).ok_or(Error::InvalidOption("track"))?;
+    let title = match &source {
+        TrackSource::YouTube { id } => match crate::track::cached_metadata(id).await {
+            Some(metadata) => metadata.title,
+            None => {
+                let url = format!("https://www.youtube.com/watch?v={}", id);
+                crate::track::fetch_metadata(&url).await?.title
+            }
+        },
+        TrackSource::Local { path } => {
+            crate::library::get(path)
+                .await
+                .ok_or(Error::NotInLibrary)?
+                .title
+        }
+        TrackSource::Radio { name, .. } => name.clone(),
+    };
+    Ok(SavedTrack { source, title })
+}
+
+async fn show_playlist(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    playlist: &crate::playlists::Playlist,
+) -> Result<()> {
+    let mut description = playlist
+        .tracks
+        .iter()
+        .take(SHOWN_TRACKS)
+        .enumerate()
+        .map(|(i, track)| format!("{}. {}", i + 1, track.title))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if playlist.tracks.len() > SHOWN_TRACKS {
+        description.push_str(&format!(
+            "\n...and {} more",
+            playlist.tracks.len() - SHOWN_TRACKS
+        ));
+    }
+    if playlist.tracks.is_empty() {
+        description = "No tracks yet".to_string();
+    }
+
+    interaction
+        .edit_original_interaction_response(&ctx.http, |edit| {
+            edit.content("").embed(|embed| {
+                embed
+                    .title(&playlist.name)
+                    .description(description)
+                    .field("Tracks", playlist.tracks.len(), true)
+                    .field("Created by", playlist.owner.mention(), true)
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn show_playlists(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+) -> Result<()> {
+    let playlists = crate::playlists::list(guild_id).await?;
+    let message = if playlists.is_empty() {
+        "There are no playlists yet. Make one with /playlist create".to_string()
+    } else {
+        playlists
+            .iter()
+            .map(|playlist| format!("{} ({} tracks)", playlist.name, playlist.tracks.len()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    crate::util::edit_response(interaction, &ctx.http, message).await
+}
@@ -1,79 +1,46 @@
+use super::Command;
+use crate::error::Result;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
-use serenity::model::application::{
-    command::CommandOptionType, interaction::application_command::CommandDataOptionValue,
-};
+use serenity::model::application::command::CommandOptionType;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::prelude::*;
+use std::time::Duration;
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    let url = match interaction.data.options.get(0) {
-        Some(url_option) => {
-            if url_option.name != "url".to_owned() {
-                crate::util::respond_to_interaction(
-                    interaction,
-                    &ctx.http,
-                    true,
-                    "Internal Error: Failed to get url from interaction",
-                )
-                .await;
-                return;
-            } else {
-                match &url_option.resolved {
-                    Some(resolved) => match resolved {
-                        CommandDataOptionValue::String(url) => url,
-                        x => {
-                            eprintln!("Unexpected type for url: {:?}", x);
-                            crate::util::respond_to_interaction(
-                                interaction,
-                                &ctx.http,
-                                true,
-                                "Internal Error: Failed to get url from interaction",
-                            )
-                            .await;
-                            return;
-                        }
-                    },
-                    None => {
-                        eprintln!("No url provided");
-                        crate::util::respond_to_interaction(
-                            interaction,
-                            &ctx.http,
-                            true,
-                            "Internal Error: Failed to get url from interaction",
-                        )
-                        .await;
-                        return;
-                    }
-                }
-            }
-        }
-        None => {
-            eprintln!("interaction.data.options length was 0. Could not get URL from interaction");
+pub struct Queue;
 
-            crate::util::respond_to_interaction(
-                interaction,
-                &ctx.http,
-                true,
-                "Must provide a valid YouTube URL",
-            )
-            .await;
+#[async_trait]
+impl Command for Queue {
+    fn name(&self) -> &'static str {
+        "queue"
+    }
 
-            return;
-        }
-    };
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Queues a song to play in the voice channel")
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("URL of the song to queue")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+    }
 
-    crate::util::play_song(ctx, interaction, url).await;
-}
+    fn cooldown(&self) -> Option<Duration> {
+        Some(Duration::from_secs(3))
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let url = crate::util::string_option(interaction, "url")?;
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
-        .name("queue")
-        .description("Queues a song to play in the voice channel")
-        .create_option(|option| {
-            option
-                .name("url")
-                .description("URL of the song to queue")
-                .kind(CommandOptionType::String)
-                .required(true)
-        })
+        crate::util::play_song(ctx, interaction, url).await
+    }
 }
@@ -0,0 +1,78 @@
+use super::Command;
+use crate::error::{Error, Result};
+use crate::track::TrackSource;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+use std::time::Duration;
+
+pub struct Radio;
+
+#[async_trait]
+impl Command for Radio {
+    fn name(&self) -> &'static str {
+        "radio"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Queues an internet radio stream")
+            .create_option(|option| {
+                option
+                    .name("station")
+                    .description("One of the built-in stations")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+                for (name, _) in crate::radio::STATIONS {
+                    option.add_string_choice(name, name);
+                }
+                option
+            })
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("URL of an Icecast, Shoutcast or HLS stream")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        Some(Duration::from_secs(3))
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        crate::util::guild_id(interaction)?;
+
+        let (name, url) = match crate::util::string_option(interaction, "url") {
+            Ok(url) => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(Error::InvalidOption("url"));
+                }
+                (url, url)
+            }
+            Err(Error::MissingOption(_)) => {
+                let station = crate::util::string_option(interaction, "station")?;
+                let url =
+                    crate::radio::station_url(station).ok_or(Error::InvalidOption("station"))?;
+                (station, url)
+            }
+            Err(e) => return Err(e),
+        };
+
+        let source = TrackSource::Radio {
+            name: name.to_string(),
+            url: url.to_string(),
+        };
+        crate::util::play_source(ctx, interaction, &source).await
+    }
+}
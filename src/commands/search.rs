@@ -1,142 +1,88 @@
+use super::Command;
+use crate::error::{Error, Result};
+use crate::track::TrackSource;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
-use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::prelude::command::CommandOptionType;
-use serenity::model::prelude::interaction::application_command::{
-    ApplicationCommandInteraction, CommandDataOptionValue,
-};
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::prelude::*;
+use std::time::Duration;
 use yt_api::search::*;
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    match interaction.guild_id {
-        Some(_) => (),
-        None => {
-            match interaction
-                .create_interaction_response(&ctx.http, |r| {
-                    r.kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|m| {
-                            m.content("This command can only be used in a server")
-                        })
-                })
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    eprintln!("Cannot respond to slash command: {}", e);
-                    return;
-                }
-            }
-        }
-    };
+pub struct Search;
 
-    let query = match interaction.data.options.get(0) {
-        Some(query_option) => {
-            if query_option.name != "query".to_owned() {
-                crate::util::respond_to_interaction(
-                    interaction,
-                    &ctx.http,
-                    true,
-                    "Internal Error: Failed to get query from interaction",
-                )
-                .await;
-                return;
-            } else {
-                match &query_option.resolved {
-                    Some(resolved) => match resolved {
-                        CommandDataOptionValue::String(query) => query,
-                        x => {
-                            eprintln!("Unexpected type for query: {:?}", x);
-                            crate::util::respond_to_interaction(
-                                interaction,
-                                &ctx.http,
-                                true,
-                                "Internal Error: Failed to get query from interaction",
-                            )
-                            .await;
-                            return;
-                        }
-                    },
-                    None => {
-                        eprintln!("No url provided");
-                        crate::util::respond_to_interaction(
-                            interaction,
-                            &ctx.http,
-                            true,
-                            "Internal Error: Failed to get query from interaction",
-                        )
-                        .await;
-                        return;
-                    }
-                }
-            }
-        }
-        None => {
-            eprintln!(
-                "interaction.data.options length was 0. Could not get query from interaction"
-            );
+#[async_trait]
+impl Command for Search {
+    fn name(&self) -> &'static str {
+        "search"
+    }
 
-            crate::util::respond_to_interaction(
-                interaction,
-                &ctx.http,
-                true,
-                "Must provide a query",
-            )
-            .await;
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Search for songs to play")
+            .create_option(|option| {
+                option
+                    .name("query")
+                    .description("Name of the video to queue")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("source")
+                    .description("Where to search, YouTube by default")
+                    .kind(CommandOptionType::String)
+                    .add_string_choice("YouTube", "youtube")
+                    .add_string_choice("Local library", "local")
+                    .required(false)
+            })
+    }
 
-            return;
-        }
-    };
+    fn cooldown(&self) -> Option<Duration> {
+        Some(Duration::from_secs(3))
+    }
 
-    let result = match SearchList::new(crate::util::YOUTUBE_API_KEY.clone())
-        .q(query)
-        .item_type(ItemType::Video)
-        .await
-    {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Failed to search YouTube: {}", e);
-            crate::util::respond_to_interaction(
-                interaction,
-                &ctx.http,
-                true,
-                "Failed to search YouTube",
-            )
-            .await;
-            return;
-        }
-    };
+    fn defer(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        crate::util::guild_id(interaction)?;
+        let query = crate::util::string_option(interaction, "query")?;
 
-    let video_id = match result.items.get(0) {
-        Some(video) => match &video.id.video_id {
-            Some(id) => id,
-            None => {
-                eprintln!("No video ID found in search result");
-                crate::util::respond_to_interaction(interaction, &ctx.http, true, "No video found")
-                    .await;
-                return;
+        if crate::util::optional_string_option(interaction, "source")? == Some("local") {
+            if crate::library::library_path().is_none() {
+                return Err(Error::LibraryDisabled);
             }
-        },
-        None => {
-            crate::util::respond_to_interaction(interaction, &ctx.http, true, "No videos found")
-                .await;
-            return;
+            let track = crate::library::search(query)
+                .await
+                .into_iter()
+                .next()
+                .ok_or(Error::NoResults)?;
+            let source = TrackSource::Local {
+                path: track.relative_path,
+            };
+            return crate::util::play_source(ctx, interaction, &source).await;
         }
-    };
 
-    let url = format!("https://www.youtube.com/watch?v={}", video_id);
+        crate::selfcheck::require(crate::selfcheck::Feature::Search)?;
+        let result = SearchList::new(crate::util::YOUTUBE_API_KEY.clone())
+            .q(query)
+            .item_type(ItemType::Video)
+            .await
+            .map_err(|e| Error::SearchFailed(e.to_string()))?;
 
-    crate::util::play_song(ctx, interaction, url.as_str()).await;
-}
+        let video_id = result
+            .items
+            .get(0)
+            .and_then(|video| video.id.video_id.as_ref())
+            .ok_or(Error::NoResults)?;
+
+        let url = format!("https://www.youtube.com/watch?v={}", video_id);
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
-        .name("search")
-        .description("Search for songs to play")
-        .create_option(|option| {
-            option
-                .name("query")
-                .description("Name of the video to queue")
-                .kind(CommandOptionType::String)
-                .required(true)
-        })
+        crate::util::play_song(ctx, interaction, url.as_str()).await
+    }
 }
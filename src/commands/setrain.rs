@@ -1,3 +1,6 @@
+use super::Command;
+use crate::error::{Error, Result};
+use serenity::async_trait;
 use serenity::{
     builder::CreateApplicationCommand,
     client::Context,
@@ -8,104 +11,61 @@ use serenity::{
 };
 use tokio::time::timeout;
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    let http = ctx.http.clone();
-    let on = match interaction.data.options.get(0) {
-        Some(option) => match option.value.as_ref() {
-            Some(value) => match value.to_string().parse::<bool>() {
-                Ok(value) => value,
-                Err(e) => {
-                    eprintln!("Failed to parce argument `on` with error {e}");
-                    crate::util::respond_to_interaction(
-                        interaction,
-                        &http,
-                        true,
-                        "Failed to parse argument `on`",
-                    )
-                    .await;
-                    return;
-                }
-            },
-            None => {
-                eprintln!("Failed to take reference to argument `on`");
-                crate::util::respond_to_interaction(
-                    interaction,
-                    &http,
-                    true,
-                    "Failed to parse argument `on`",
-                )
-                .await;
-                return;
-            }
-        },
-        None => {
-            crate::util::respond_to_interaction(
-                interaction,
-                &http,
-                true,
-                "Missing required argument `on`",
-            )
-            .await;
-            return;
-        }
-    };
+pub struct SetRain;
 
-    let guild_id = match interaction.guild_id {
-        Some(id) => id.to_string(),
-        None => {
-            crate::util::respond_to_interaction(
-                interaction,
-                &http,
-                true,
-                "This command can only be used in a guild",
-            )
-            .await;
-            return;
+#[async_trait]
+impl Command for SetRain {
+    fn name(&self) -> &'static str {
+        "setrain"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Sets the rain effect")
+            .create_option(|option| {
+                option
+                    .name("on")
+                    .description("Turns the rain effect on/off")
+                    .kind(CommandOptionType::Boolean)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("fade")
+                    .description("Fades the rain out at the end of each track (off by default)")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            })
+    }
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let on = crate::util::bool_option(interaction, "on")?;
+        let fade = crate::util::optional_bool_option(interaction, "fade")?;
+        let guild_id = crate::util::guild_id(interaction)?;
+        if on {
+            crate::selfcheck::require(crate::selfcheck::Feature::Rain)?;
         }
-    };
 
-    {
-        let mut rain_enabled_hashmap = match timeout(
-            std::time::Duration::from_secs(5),
-            crate::util::RAIN_ENABLED.lock(),
-        )
-        .await
         {
-            Ok(lock) => lock,
-            Err(_) => {
-                eprintln!("Failed to get lock on rain_enabled hashmap");
-                crate::util::respond_to_interaction(
-                    interaction,
-                    &http,
-                    true,
-                    "There was an internal error. Please try again later",
-                )
-                .await;
-                return;
-            }
-        };
-
-        rain_enabled_hashmap.insert(guild_id, on);
-    }
+            let mut rain_enabled_hashmap = timeout(
+                std::time::Duration::from_secs(5),
+                crate::util::RAIN_ENABLED.lock(),
+            )
+            .await
+            .map_err(|_| Error::LockTimeout)?;
 
-    crate::util::respond_to_interaction(
-        interaction,
-        &http,
-        false,
-        format!("User {} set rain to {}", interaction.user.tag(), on),
-    )
-    .await;
-}
+            rain_enabled_hashmap.insert(guild_id.to_string(), on);
+        }
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
-        .name("setrain")
-        .description("Sets the rain effect")
-        .create_option(|option| {
-            option
-                .name("on")
-                .description("Turns the rain effect on/off")
-                .kind(CommandOptionType::Boolean)
-                .required(true)
-        })
+        let mut message = format!("User {} set rain to {}", interaction.user.tag(), on);
+        if let Some(fade) = fade {
+            crate::settings::update_guild_settings(guild_id, |settings| settings.rain_fade = fade)
+                .await?;
+            message.push_str(&format!(", fading out at the end of each track: {}", fade));
+        }
+        crate::util::respond_to_interaction(interaction, &ctx.http, false, message).await
+    }
 }
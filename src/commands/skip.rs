@@ -1,73 +1,40 @@
+use super::Command;
+use crate::error::Result;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
-use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::prelude::*;
-use tokio::time::timeout;
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) {
-    let guild_id = match interaction.guild_id {
-        Some(id) => id,
-        None => {
-            match interaction
-                .create_interaction_response(&ctx.http, |r| {
-                    r.kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|m| {
-                            m.content("This command can only be used in a server")
-                        })
-                })
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    eprintln!("Cannot respond to slash command: {}", e);
-                    return;
-                }
-            }
-        }
-    };
-    let http = ctx.http.clone();
+pub struct Skip;
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+#[async_trait]
+impl Command for Skip {
+    fn name(&self) -> &'static str {
+        "skip"
+    }
+
+    fn register<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command.description("Skips the current song")
+    }
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = match timeout(std::time::Duration::from_secs(5), handler_lock.lock()).await {
-            Ok(handler) => handler,
-            Err(e) => {
-                eprintln!("Failed to lock handler with error {}", e);
-                crate::util::respond_to_interaction(
-                    interaction,
-                    &ctx.http,
-                    true,
-                    "There was an error. Please try again later.",
-                )
-                .await;
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+        let guild_id = crate::util::guild_id(interaction)?;
+        let handler_lock = crate::util::current_call(ctx, guild_id).await?;
+        let handler = crate::util::lock_call(&handler_lock).await?;
 
-                return;
-            }
-        };
         let queue = handler.queue();
+        let skipped = match queue.current() {
+            Some(track) => crate::track::metadata_of(&track).await,
+            None => None,
+        };
         let _ = queue.skip();
-        crate::util::respond_to_interaction(
-            interaction,
-            &http,
-            false,
-            format!("Song skipped: {} in queue.", queue.len()),
-        )
-        .await;
-    } else {
-        crate::util::respond_to_interaction(
-            interaction,
-            &http,
-            true,
-            "Not in a voice channel to play in",
-        )
-        .await;
+        let content = match skipped {
+            Some(metadata) => format!("Skipped {}: {} in queue.", metadata.title, queue.len()),
+            None => format!("Song skipped: {} in queue.", queue.len()),
+        };
+        crate::util::respond_to_interaction(interaction, &ctx.http, false, content).await
     }
 }
-
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command.name("skip").description("Skips the current song")
-}
@@ -0,0 +1,94 @@
+use crate::error::Result;
+use serenity::async_trait;
+use serenity::model::id::GuildId;
+use serenity::prelude::Context;
+use songbird::tracks::TrackHandle;
+use songbird::{Event, EventContext, EventHandler};
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+/// How often the volumes are updated during a crossfade.
+const STEP: Duration = Duration::from_millis(50);
+
+/// Longest crossfade that can be configured, in seconds.
+pub const MAX_CROSSFADE_SECS: u64 = 12;
+
+/// How long a crossfade should last for a track of length `duration`, so
+/// that fading in and fading out never overlap on a short track.
+pub fn fade_length(crossfade: Duration, duration: Duration) -> Duration {
+    crossfade.min(duration / 2)
+}
+
+/// Starts the next track in the queue while this one is fading out.
+///
+/// Added as a delayed event, so that it fires the fade length before the end
+/// of the track. The builtin queue moves on to the next track as usual once
+/// this one ends; it is just already playing by then.
+pub struct Crossfade {
+    pub ctx: Context,
+    pub guild_id: GuildId,
+    pub fade: Duration,
+}
+
+#[async_trait]
+impl EventHandler for Crossfade {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let outgoing = match ctx {
+            EventContext::Track([(_, handle), ..]) => (*handle).clone(),
+            _ => return None,
+        };
+
+        let ctx = self.ctx.clone();
+        let guild_id = self.guild_id;
+        let fade = self.fade;
+        tokio::spawn(async move {
+            if let Err(e) = fade_into_next(&ctx, guild_id, outgoing, fade).await {
+                tracing::warn!(guild_id = %guild_id, error = %e, "crossfade failed");
+            }
+        });
+        None
+    }
+}
+
+async fn fade_into_next(
+    ctx: &Context,
+    guild_id: GuildId,
+    outgoing: TrackHandle,
+    fade: Duration,
+) -> Result<()> {
+    let handler_lock = crate::util::current_call(ctx, guild_id).await?;
+    let incoming = {
+        let handler = crate::util::lock_call(&handler_lock).await?;
+        match handler.queue().current_queue().as_slice() {
+            // The outgoing track may have been skipped in the meantime.
+            [current, next, ..] if current.uuid() == outgoing.uuid() => next.clone(),
+            _ => return Ok(()),
+        }
+    };
+
+    if incoming.set_volume(0.0).is_err() || incoming.play().is_err() {
+        return Ok(());
+    }
+    // Fade relative to the volumes loudness normalization gave the tracks.
+    let outgoing_volume = crate::loudness::volume_of(&outgoing).await;
+    let incoming_volume = crate::loudness::volume_of(&incoming).await;
+
+    // Equal-power curves: the two tracks' gains squared always add up to 1.
+    // Both carry their own, unrelated stretch of rain, so the ambience stays
+    // at the same level all the way through instead of dipping in the middle.
+    let steps = (fade.as_millis() / STEP.as_millis()).max(1) as u32;
+    let mut interval = tokio::time::interval(STEP);
+    for step in 1..=steps {
+        interval.tick().await;
+        let angle = step as f32 / steps as f32 * FRAC_PI_2;
+        if outgoing.set_volume(outgoing_volume * angle.cos()).is_err() {
+            // Skipped or stopped mid-fade.
+            break;
+        }
+        if incoming.set_volume(incoming_volume * angle.sin()).is_err() {
+            return Ok(());
+        }
+    }
+    let _ = incoming.set_volume(incoming_volume);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+/// Sample rate the speed effects resample to.
+const SAMPLE_RATE: u32 = 48000;
+
+/// Playback speed, which shifts the pitch along with it like a record played
+/// at the wrong speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Speed {
+    #[default]
+    Normal,
+    Slowed,
+    Nightcore,
+}
+
+impl Speed {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "normal" => Some(Speed::Normal),
+            "slowed" => Some(Speed::Slowed),
+            "nightcore" => Some(Speed::Nightcore),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Speed::Normal => "normal",
+            Speed::Slowed => "slowed",
+            Speed::Nightcore => "nightcore",
+        }
+    }
+
+    /// How much faster than normal the track plays.
+    pub fn factor(&self) -> f64 {
+        match self {
+            Speed::Normal => 1.0,
+            Speed::Slowed => 0.85,
+            Speed::Nightcore => 1.25,
+        }
+    }
+}
+
+/// Processing applied to the music, but not the rain, as it is mixed.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Effects {
+    /// Low-pass filter, like hearing the music through a wall.
+    pub muffled: bool,
+    pub reverb: bool,
+    pub speed: Speed,
+}
+
+impl Effects {
+    pub fn is_empty(&self) -> bool {
+        *self == Effects::default()
+    }
+
+    /// The effects that still work on a live stream. Changing the speed would
+    /// make playback run ahead of or fall behind the stream.
+    pub fn for_live(&self) -> Effects {
+        Effects {
+            speed: Speed::Normal,
+            ..self.clone()
+        }
+    }
+
+    /// An ffmpeg filter chain applying the effects, or `None` if there are
+    /// none.
+    pub fn filter(&self) -> Option<String> {
+        let mut filters = Vec::new();
+        if self.speed != Speed::Normal {
+            filters.push(format!(
+                "aresample={rate},asetrate={rate}*{factor},aresample={rate}",
+                rate = SAMPLE_RATE,
+                factor = self.speed.factor()
+            ));
+        }
+        if self.muffled {
+            // Twice for a steeper slope.
+            filters.push("lowpass=f=600,lowpass=f=600".to_string());
+        }
+        if self.reverb {
+            filters.push("aecho=0.8:0.85:60|110|170:0.4|0.3|0.2".to_string());
+        }
+
+        if filters.is_empty() {
+            None
+        } else {
+            Some(filters.join(","))
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.muffled {
+            names.push("muffled");
+        }
+        if self.reverb {
+            names.push("reverb");
+        }
+        if self.speed != Speed::Normal {
+            names.push(self.speed.name());
+        }
+        names
+    }
+
+    /// Describes the effects to users.
+    pub fn describe(&self) -> String {
+        if self.is_empty() {
+            "none".to_string()
+        } else {
+            self.names().join(", ")
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use songbird::error::JoinError;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can make a command fail.
+///
+/// The `Display` output is meant for logs. What the user gets to see comes
+/// from [`Error::user_message`].
+#[derive(Debug)]
+pub enum Error {
+    UnknownCommand,
+    MissingPermissions,
+    Cooldown(std::time::Duration),
+    NotInGuild,
+    NotInVoice,
+    NotConnected,
+    NothingPlaying,
+    GuildNotCached,
+    MissingOption(&'static str),
+    InvalidOption(&'static str),
+    InvalidUrl,
+    TrackRejected(String),
+    JoinFailed(JoinError),
+    Voice(JoinError),
+    LockTimeout,
+    SearchFailed(String),
+    NoResults,
+    LibraryDisabled,
+    /// Turned off at startup, because what it needs failed its check.
+    Unavailable(crate::selfcheck::Feature),
+    NotInLibrary,
+    UnknownPlaylist(String),
+    PlaylistExists(String),
+    EmptyPlaylist(String),
+    NoFavourites,
+    DownloadFailed(String),
+    /// yt-dlp failed, for the reason classified from its stderr, which is
+    /// kept for the logs.
+    YtDlp(crate::ytdlp::Failure, String),
+    MixFailed(String),
+    SourceFailed(songbird::input::error::Error),
+    Config(String),
+    Storage(String),
+    Discord(serenity::Error),
+}
+
+impl Error {
+    /// Whether the error was caused by how the command was used, as opposed
+    /// to something going wrong on our end.
+    pub fn is_user_error(&self) -> bool {
+        if let Error::YtDlp(failure, _) = self {
+            return failure.is_unavailable();
+        }
+        matches!(
+            self,
+            Error::UnknownCommand
+                | Error::MissingPermissions
+                | Error::Cooldown(_)
+                | Error::NotInGuild
+                | Error::NotInVoice
+                | Error::NotConnected
+                | Error::NothingPlaying
+                | Error::MissingOption(_)
+                | Error::InvalidOption(_)
+                | Error::InvalidUrl
+                | Error::TrackRejected(_)
+                | Error::NoResults
+                | Error::LibraryDisabled
+                | Error::Unavailable(_)
+                | Error::NotInLibrary
+                | Error::UnknownPlaylist(_)
+                | Error::PlaylistExists(_)
+                | Error::EmptyPlaylist(_)
+                | Error::NoFavourites
+        )
+    }
+
+    pub fn user_message(&self) -> String {
+        match self {
+            Error::UnknownCommand => "Unknown command".to_string(),
+            Error::MissingPermissions => {
+                "You don't have permission to use this command".to_string()
+            }
+            Error::Cooldown(remaining) => format!(
+                "Please wait {} more seconds before using this command again",
+                remaining.as_secs() + 1
+            ),
+            Error::NotInGuild => "This command can only be used in a server".to_string(),
+            Error::NotInVoice => "You must be in a voice channel to use this command".to_string(),
+            Error::NotConnected => "I'm not in a voice channel".to_string(),
+            Error::NothingPlaying => "Nothing is playing".to_string(),
+            Error::MissingOption(name) => format!("Missing required argument `{}`", name),
+            Error::InvalidOption(name) => format!("Failed to parse argument `{}`", name),
+            Error::InvalidUrl => "Must provide a valid YouTube URL".to_string(),
+            Error::TrackRejected(reason) => reason.clone(),
+            Error::JoinFailed(_) => "Failed to join voice channel".to_string(),
+            Error::SearchFailed(_) => "Failed to search YouTube".to_string(),
+            Error::NoResults => "No results found".to_string(),
+            Error::LibraryDisabled => "There is no local library set up".to_string(),
+            Error::Unavailable(feature) => feature.user_message().to_string(),
+            Error::NotInLibrary => "That track is no longer in the library".to_string(),
+            Error::UnknownPlaylist(name) => format!("There is no playlist called `{}`", name),
+            Error::PlaylistExists(name) => format!("A playlist called `{}` already exists", name),
+            Error::EmptyPlaylist(name) => format!("The playlist `{}` is empty", name),
+            Error::NoFavourites => {
+                "You don't have any favourites yet. Save what is playing with /fav add".to_string()
+            }
+            Error::DownloadFailed(_) => "Error downloading video/audio".to_string(),
+            Error::YtDlp(failure, _) => failure.user_message().to_string(),
+            Error::MixFailed(_) => "Error mixing audio".to_string(),
+            Error::SourceFailed(_) => "Error sourcing ffmpeg".to_string(),
+            Error::GuildNotCached
+            | Error::Voice(_)
+            | Error::LockTimeout
+            | Error::Config(_)
+            | Error::Storage(_)
+            | Error::Discord(_) => {
+                "There was an internal error. Please try again later".to_string()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCommand => write!(f, "unknown command"),
+            Error::MissingPermissions => write!(f, "member lacks the required permissions"),
+            Error::Cooldown(remaining) => write!(f, "on cooldown for another {:?}", remaining),
+            Error::NotInGuild => write!(f, "command used outside of a guild"),
+            Error::NotInVoice => write!(f, "user is not in a voice channel"),
+            Error::NotConnected => write!(f, "bot is not in a voice channel"),
+            Error::NothingPlaying => write!(f, "nothing is playing"),
+            Error::GuildNotCached => write!(f, "guild missing from cache"),
+            Error::MissingOption(name) => write!(f, "missing option `{}`", name),
+            Error::InvalidOption(name) => write!(f, "invalid option `{}`", name),
+            Error::InvalidUrl => write!(f, "invalid YouTube URL"),
+            Error::TrackRejected(reason) => write!(f, "track rejected: {}", reason),
+            Error::JoinFailed(e) => write!(f, "failed to join voice channel: {}", e),
+            Error::Voice(e) => write!(f, "voice connection error: {}", e),
+            Error::LockTimeout => write!(f, "timed out waiting for a lock"),
+            Error::SearchFailed(e) => write!(f, "YouTube search failed: {}", e),
+            Error::NoResults => write!(f, "search returned no results"),
+            Error::LibraryDisabled => write!(f, "LIBRARY_PATH is not set"),
+            Error::Unavailable(feature) => write!(f, "{} is disabled", feature),
+            Error::NotInLibrary => write!(f, "track missing from the library index"),
+            Error::UnknownPlaylist(name) => write!(f, "unknown playlist `{}`", name),
+            Error::PlaylistExists(name) => write!(f, "playlist `{}` already exists", name),
+            Error::EmptyPlaylist(name) => write!(f, "playlist `{}` is empty", name),
+            Error::NoFavourites => write!(f, "user has no favourites"),
+            Error::DownloadFailed(e) => write!(f, "download failed: {}", e),
+            Error::YtDlp(failure, stderr) => write!(f, "yt-dlp failed ({:?}): {}", failure, stderr),
+            Error::MixFailed(e) => write!(f, "mixing failed: {}", e),
+            Error::SourceFailed(e) => write!(f, "failed to create audio source: {:?}", e),
+            Error::Config(e) => write!(f, "configuration error: {}", e),
+            Error::Storage(e) => write!(f, "storage error: {}", e),
+            Error::Discord(e) => write!(f, "Discord API error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serenity::Error> for Error {
+    fn from(e: serenity::Error) -> Self {
+        Error::Discord(e)
+    }
+}
+
+impl From<JoinError> for Error {
+    fn from(e: JoinError) -> Self {
+        Error::Voice(e)
+    }
+}
+
+impl From<songbird::input::error::Error> for Error {
+    fn from(e: songbird::input::error::Error) -> Self {
+        Error::SourceFailed(e)
+    }
+}
@@ -0,0 +1,50 @@
+use crate::error::{Error, Result};
+use crate::store::Store;
+use crate::track::SavedTrack;
+use lazy_static::lazy_static;
+use serenity::model::id::UserId;
+use std::collections::HashMap;
+
+/// Most tracks a user can have as favourites.
+pub const MAX_FAVOURITES: usize = 200;
+
+lazy_static! {
+    /// Favourites by user id, so they follow users from guild to guild.
+    static ref FAVOURITES: Store<HashMap<String, Vec<SavedTrack>>> = Store::open("favourites");
+}
+
+/// Adds `track` to the favourites of `user_id`.
+///
+/// Returns `false` if it was already one of them.
+pub async fn add(user_id: UserId, track: SavedTrack) -> Result<bool> {
+    FAVOURITES
+        .update(|favourites| {
+            let favourites = favourites.entry(user_id.to_string()).or_default();
+            if favourites
+                .iter()
+                .any(|favourite| favourite.source == track.source)
+            {
+                return Ok(false);
+            }
+            if favourites.len() >= MAX_FAVOURITES {
+                return Err(Error::TrackRejected(format!(
+                    "You can have at most {} favourites",
+                    MAX_FAVOURITES
+                )));
+            }
+            favourites.push(track);
+            Ok(true)
+        })
+        .await?
+}
+
+pub async fn list(user_id: UserId) -> Result<Vec<SavedTrack>> {
+    FAVOURITES
+        .read(|favourites| {
+            favourites
+                .get(&user_id.to_string())
+                .cloned()
+                .unwrap_or_default()
+        })
+        .await
+}
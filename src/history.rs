@@ -0,0 +1,95 @@
+use crate::error::Result;
+use crate::store::Store;
+use crate::track::SavedTrack;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::model::id::{GuildId, UserId};
+use songbird::{Event, EventContext, EventHandler};
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many plays are remembered per guild.
+const MAX_ENTRIES: usize = 500;
+
+lazy_static! {
+    /// Plays by guild id, oldest first.
+    static ref HISTORY: Store<HashMap<String, VecDeque<HistoryEntry>>> = Store::open("history");
+}
+
+/// A track that was played in a guild.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// Tells plays apart, even ones of the same track that started in the
+    /// same second.
+    pub id: u64,
+    pub track: SavedTrack,
+    pub requester: UserId,
+    /// When the track started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    pub rain_enabled: bool,
+}
+
+/// Adds a track to the history of its guild once it starts playing.
+pub struct RecordPlay {
+    pub guild_id: GuildId,
+    pub track: SavedTrack,
+    pub requester: UserId,
+    pub rain_enabled: bool,
+}
+
+#[async_trait]
+impl EventHandler for RecordPlay {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let entry = HistoryEntry {
+            id: rand::random(),
+            track: self.track.clone(),
+            requester: self.requester,
+            started_at,
+            rain_enabled: self.rain_enabled,
+        };
+        if let Err(e) = record(self.guild_id, entry).await {
+            tracing::error!(guild_id = %self.guild_id, error = %e, "failed to record play");
+        }
+
+        // Play fires again whenever the track is resumed, which isn't a new
+        // play.
+        Some(Event::Cancel)
+    }
+}
+
+async fn record(guild_id: GuildId, entry: HistoryEntry) -> Result<()> {
+    HISTORY
+        .update(|history| {
+            let history = history.entry(guild_id.to_string()).or_default();
+            history.push_back(entry);
+            while history.len() > MAX_ENTRIES {
+                history.pop_front();
+            }
+        })
+        .await
+}
+
+/// The plays in `guild_id`, newest first, optionally only those `requester`
+/// asked for.
+pub async fn list(guild_id: GuildId, requester: Option<UserId>) -> Result<Vec<HistoryEntry>> {
+    HISTORY
+        .read(|history| {
+            history
+                .get(&guild_id.to_string())
+                .map(|history| {
+                    history
+                        .iter()
+                        .rev()
+                        .filter(|entry| requester.map_or(true, |user| entry.requester == user))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .await
+}
@@ -0,0 +1,196 @@
+use crate::track::TrackMetadata;
+use lazy_static::lazy_static;
+use lofty::{Accessor, AudioFile, TaggedFileExt};
+use serenity::prelude::RwLock;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// File extensions picked up when indexing the library.
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "opus", "m4a", "wav"];
+
+lazy_static! {
+    static ref LIBRARY: RwLock<Vec<LibraryTrack>> = RwLock::new(Vec::new());
+}
+
+/// A file in the local music library.
+#[derive(Clone, Debug)]
+pub struct LibraryTrack {
+    pub path: PathBuf,
+    /// Path relative to the library root, which identifies the track.
+    pub relative_path: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub duration: Option<f64>,
+}
+
+impl LibraryTrack {
+    pub fn metadata(&self) -> TrackMetadata {
+        TrackMetadata {
+            id: format!("local:{}", self.relative_path),
+            title: self.title.clone(),
+            channel: self.artist.clone(),
+            uploader: None,
+            duration: self.duration,
+            thumbnail: None,
+            webpage_url: None,
+            chapters: None,
+            is_live: Some(false),
+            filesize: None,
+            filesize_approx: None,
+            loudness: None,
+        }
+    }
+
+    /// Name the track's mixed audio is cached under.
+    pub fn cache_id(&self) -> String {
+        format!(
+            "local_{:016x}",
+            crate::util::stable_hash(&self.relative_path)
+        )
+    }
+
+    fn matches(&self, terms: &[String]) -> bool {
+        let haystack = format!(
+            "{} {} {}",
+            self.title,
+            self.artist.as_deref().unwrap_or_default(),
+            self.relative_path
+        )
+        .to_lowercase();
+        terms.iter().all(|term| haystack.contains(term.as_str()))
+    }
+}
+
+/// The library directory, if one is configured with `LIBRARY_PATH`.
+pub fn library_path() -> Option<PathBuf> {
+    std::env::var("LIBRARY_PATH").ok().map(PathBuf::from)
+}
+
+/// Rescans the library directory in the background.
+pub fn index_in_background() {
+    let root = match library_path() {
+        Some(root) => root,
+        None => return,
+    };
+
+    tokio::spawn(async move {
+        let scan_root = root.clone();
+        let tracks = match tokio::task::spawn_blocking(move || scan(&scan_root)).await {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                tracing::warn!(root = %root.display(), error = %e, "failed to index library");
+                return;
+            }
+        };
+        tracing::info!(root = %root.display(), tracks = tracks.len(), "indexed library");
+        *LIBRARY.write().await = tracks;
+    });
+}
+
+/// Finds the audio files under `root`, following symlinks.
+fn scan(root: &Path) -> Vec<LibraryTrack> {
+    let mut tracks = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    // Where each directory really is, so that a symlink back up the tree
+    // isn't followed round forever.
+    let mut visited = HashSet::new();
+    while let Some(directory) = directories.pop() {
+        match std::fs::canonicalize(&directory) {
+            Ok(canonical) => {
+                if !visited.insert(canonical) {
+                    continue;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(path = %directory.display(), error = %e, "failed to resolve library directory");
+                continue;
+            }
+        }
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!(path = %directory.display(), error = %e, "failed to read library directory");
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if is_audio_file(&path) {
+                tracks.push(read_track(root, path));
+            }
+        }
+    }
+    tracks.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    tracks
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| AUDIO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn read_track(root: &Path, path: PathBuf) -> LibraryTrack {
+    let relative_path = path
+        .strip_prefix(root)
+        .unwrap_or(&path)
+        .to_string_lossy()
+        .to_string();
+    let file_stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| relative_path.clone());
+
+    let (title, artist, duration) = match lofty::read_from_path(&path) {
+        Ok(tagged_file) => {
+            let tag = tagged_file
+                .primary_tag()
+                .or_else(|| tagged_file.first_tag());
+            (
+                tag.and_then(|tag| tag.title().map(|title| title.to_string())),
+                tag.and_then(|tag| tag.artist().map(|artist| artist.to_string())),
+                Some(tagged_file.properties().duration().as_secs_f64()),
+            )
+        }
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "failed to read tags");
+            (None, None, None)
+        }
+    };
+
+    LibraryTrack {
+        path,
+        relative_path,
+        title: title.unwrap_or(file_stem),
+        artist,
+        duration,
+    }
+}
+
+/// Tracks whose title, artist or path contain every word of `query`.
+pub async fn search(query: &str) -> Vec<LibraryTrack> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| term.to_lowercase())
+        .collect();
+    LIBRARY
+        .read()
+        .await
+        .iter()
+        .filter(|track| track.matches(&terms))
+        .cloned()
+        .collect()
+}
+
+/// The track at `relative_path`, if it's still in the library.
+pub async fn get(relative_path: &str) -> Option<LibraryTrack> {
+    LIBRARY
+        .read()
+        .await
+        .iter()
+        .find(|track| track.relative_path == relative_path)
+        .cloned()
+}
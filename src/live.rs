@@ -0,0 +1,186 @@
+use crate::effects::Effects;
+use crate::track::TrackMetadata;
+use serenity::async_trait;
+use songbird::input::{
+    children_to_reader, error::Error as InputError, error::Result as InputResult,
+    restartable::Restart, Codec, Container, Input, Metadata,
+};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// Where a live stream's audio comes from.
+enum Origin {
+    /// A YouTube live stream, resolved and fetched by yt-dlp.
+    YouTube,
+    /// An Icecast/Shoutcast/HLS stream ffmpeg can read directly.
+    Direct,
+}
+
+/// A live stream decoded by ffmpeg, optionally with the rain looped
+/// underneath it.
+///
+/// Nothing is spawned until the track actually starts, so a stream waiting in
+/// the queue doesn't fall behind.
+pub struct LiveStream {
+    url: String,
+    origin: Origin,
+    rain_path: Option<String>,
+    effects: Effects,
+    metadata: Metadata,
+}
+
+impl LiveStream {
+    /// A YouTube live stream, piped from yt-dlp into ffmpeg.
+    pub fn youtube(
+        url: &str,
+        rain_path: Option<String>,
+        effects: Effects,
+        track: &TrackMetadata,
+    ) -> Self {
+        Self::new(url, Origin::YouTube, rain_path, effects, track)
+    }
+
+    /// A radio stream, read by ffmpeg itself.
+    pub fn direct(
+        url: &str,
+        rain_path: Option<String>,
+        effects: Effects,
+        track: &TrackMetadata,
+    ) -> Self {
+        Self::new(url, Origin::Direct, rain_path, effects, track)
+    }
+
+    fn new(
+        url: &str,
+        origin: Origin,
+        rain_path: Option<String>,
+        effects: Effects,
+        track: &TrackMetadata,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            origin,
+            rain_path,
+            effects,
+            metadata: Metadata {
+                title: Some(track.title.clone()),
+                channel: track.channel_name().map(str::to_string),
+                source_url: track.webpage_url.clone(),
+                thumbnail: track.thumbnail.clone(),
+                channels: Some(2),
+                sample_rate: Some(48000),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn spawn_ffmpeg(&self, input: &str, stdin: Stdio, rain_gain: f64) -> std::io::Result<Child> {
+        let mut ffmpeg = Command::new("ffmpeg");
+        ffmpeg.args(["-v", "error"]);
+        if let Origin::Direct = self.origin {
+            // Radio servers drop listeners now and then; pick the stream back up.
+            ffmpeg.args([
+                "-reconnect",
+                "1",
+                "-reconnect_streamed",
+                "1",
+                "-reconnect_delay_max",
+                "5",
+            ]);
+        }
+        ffmpeg.args(["-i", input]);
+        let effects = self.effects.filter();
+        match &self.rain_path {
+            Some(rain_path) => {
+                let filter = format!(
+                    "[0:a]volume=1{}[a0];[1:a]volume={:.2}dB[a1];[a0][a1]amix=inputs=2:duration=first:normalize=0[a]",
+                    effects
+                        .map(|filter| format!(",{}", filter))
+                        .unwrap_or_default(),
+                    rain_gain
+                );
+                ffmpeg.args([
+                    "-stream_loop",
+                    "-1",
+                    "-i",
+                    rain_path.as_str(),
+                    "-filter_complex",
+                    filter.as_str(),
+                    "-map",
+                    "[a]",
+                ]);
+            }
+            None => {
+                ffmpeg.args(["-map", "0:a"]);
+                if let Some(effects) = &effects {
+                    ffmpeg.args(["-af", effects.as_str()]);
+                }
+            }
+        }
+        ffmpeg
+            .args([
+                "-f",
+                "f32le",
+                "-ac",
+                "2",
+                "-ar",
+                "48000",
+                "-acodec",
+                "pcm_f32le",
+                "-",
+            ])
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+    }
+}
+
+#[async_trait]
+impl Restart for LiveStream {
+    // Live streams can't be seeked, so a restart always joins the stream
+    // wherever it currently is.
+    async fn call_restart(&mut self, _time: Option<Duration>) -> InputResult<Input> {
+        // There is no telling how loud a stream is ahead of time, but the rain
+        // can still be put at the same level as under cached tracks.
+        let rain_gain = match &self.rain_path {
+            Some(rain_path) => crate::loudness::rain_gain_db(rain_path).await,
+            None => 0.0,
+        };
+        let children = match self.origin {
+            Origin::YouTube => {
+                let mut ytdl = crate::ytdlp::blocking_command()
+                    .args(["-f", "ba/b", "-q", "-o", "-", self.url.as_str()])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(InputError::Io)?;
+                let ytdl_stdout = ytdl.stdout.take().ok_or(InputError::Stdout)?;
+
+                match self.spawn_ffmpeg("pipe:0", Stdio::from(ytdl_stdout), rain_gain) {
+                    Ok(ffmpeg) => vec![ytdl, ffmpeg],
+                    Err(e) => {
+                        let _ = ytdl.kill();
+                        return Err(InputError::Io(e));
+                    }
+                }
+            }
+            Origin::Direct => vec![self
+                .spawn_ffmpeg(&self.url, Stdio::null(), rain_gain)
+                .map_err(InputError::Io)?],
+        };
+
+        Ok(Input::new(
+            true,
+            children_to_reader::<f32>(children),
+            Codec::FloatPcm,
+            Container::Raw,
+            Some(self.metadata.clone()),
+        ))
+    }
+
+    async fn lazy_init(&mut self) -> InputResult<(Option<Metadata>, Codec, Container)> {
+        Ok((Some(self.metadata.clone()), Codec::FloatPcm, Container::Raw))
+    }
+}
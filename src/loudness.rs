@@ -0,0 +1,153 @@
+use crate::error::{Error, Result};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::prelude::{Mutex, TypeMapKey};
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+
+/// Integrated loudness every track is brought to, in LUFS. Quieter than
+/// streaming services aim for, to suit falling asleep to.
+pub const TARGET_LUFS: f64 = -18.0;
+
+/// Integrated loudness the rain is brought to, so it sits under the music.
+pub const RAIN_TARGET_LUFS: f64 = -26.0;
+
+/// Highest true peak a track may be raised to, in dBTP.
+const TRUE_PEAK_CEILING: f64 = -1.0;
+
+/// Largest gain applied either way, so that a near-silent intro or a broken
+/// analysis can't blow up the volume.
+const MAX_GAIN_DB: f64 = 12.0;
+
+/// The volume the rain was mixed at before it was normalized.
+const FALLBACK_RAIN_GAIN_DB: f64 = -2.5;
+
+lazy_static! {
+    /// Gain for each ambience file by its [`crate::cache::file_fingerprint`],
+    /// analysed the first time it is mixed. Keyed like the mix cache, so that
+    /// a file replaced under the same path is measured again.
+    static ref RAIN_GAINS: Mutex<HashMap<String, f64>> = Mutex::new(HashMap::new());
+}
+
+/// EBU R128 measurements of a track, as reported by ffmpeg's loudnorm filter.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// True peak in dBTP.
+    pub true_peak: f64,
+}
+
+impl Loudness {
+    /// The gain in dB that brings the track to `target` without pushing its
+    /// peaks over the ceiling.
+    pub fn gain_db(&self, target: f64) -> f64 {
+        (target - self.integrated)
+            .min(TRUE_PEAK_CEILING - self.true_peak)
+            .clamp(-MAX_GAIN_DB, MAX_GAIN_DB)
+    }
+}
+
+pub fn db_to_linear(gain_db: f64) -> f32 {
+    10f64.powf(gain_db / 20.0) as f32
+}
+
+/// The volume a track with `loudness` plays at. With rain on, the gain is
+/// mixed into the audio along with the rain instead.
+pub fn track_volume(loudness: Option<Loudness>, rain_enabled: bool) -> f32 {
+    match loudness {
+        Some(loudness) if !rain_enabled => db_to_linear(loudness.gain_db(TARGET_LUFS)),
+        _ => 1.0,
+    }
+}
+
+/// The volume a track plays at when it isn't being faded, for tracks whose
+/// gain couldn't be mixed into the audio itself.
+pub struct VolumeKey;
+
+impl TypeMapKey for VolumeKey {
+    type Value = f32;
+}
+
+pub async fn volume_of(track: &TrackHandle) -> f32 {
+    track
+        .typemap()
+        .read()
+        .await
+        .get::<VolumeKey>()
+        .copied()
+        .unwrap_or(1.0)
+}
+
+/// Measures the loudness of the audio file at `path`.
+pub async fn analyze(path: &str) -> Result<Loudness> {
+    let output = tokio::process::Command::new("ffmpeg")
+        .args([
+            "-hide_banner",
+            "-nostats",
+            "-i",
+            path,
+            "-af",
+            "loudnorm=print_format=json",
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .await
+        .map_err(|e| {
+            Error::MixFailed(format!("failed to spawn ffmpeg to measure loudness: {}", e))
+        })?;
+    if !output.status.success() {
+        return Err(Error::MixFailed(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    parse_loudnorm(&String::from_utf8_lossy(&output.stderr)).ok_or_else(|| {
+        Error::MixFailed(format!("failed to parse loudness measurement of {}", path))
+    })
+}
+
+/// Picks the measurements out of the JSON loudnorm prints at the end of
+/// ffmpeg's log.
+fn parse_loudnorm(log: &str) -> Option<Loudness> {
+    #[derive(Deserialize)]
+    struct Measurement {
+        input_i: String,
+        input_tp: String,
+    }
+
+    let json = &log[log.rfind('{')?..];
+    let json = &json[..=json.find('}')?];
+    let measurement: Measurement = serde_json::from_str(json).ok()?;
+    let loudness = Loudness {
+        integrated: measurement.input_i.parse().ok()?,
+        true_peak: measurement.input_tp.parse().ok()?,
+    };
+    // Silence measures as -inf.
+    if loudness.integrated.is_finite() && loudness.true_peak.is_finite() {
+        Some(loudness)
+    } else {
+        None
+    }
+}
+
+/// The gain in dB to mix the ambience at `rain_path` with.
+pub async fn rain_gain_db(rain_path: &str) -> f64 {
+    let key = crate::cache::file_fingerprint(rain_path).unwrap_or_else(|_| rain_path.to_string());
+    let mut gains = RAIN_GAINS.lock().await;
+    if let Some(gain) = gains.get(&key) {
+        return *gain;
+    }
+
+    let gain = match analyze(rain_path).await {
+        Ok(loudness) => loudness.gain_db(RAIN_TARGET_LUFS),
+        Err(e) => {
+            tracing::warn!(path = rain_path, error = %e, "failed to measure rain loudness");
+            FALLBACK_RAIN_GAIN_DB
+        }
+    };
+    gains.insert(key, gain);
+    gain
+}
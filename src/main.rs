@@ -8,9 +8,30 @@ use serenity::{async_trait, client::Client};
 use songbird::SerenityInit;
 use std::env;
 
+pub mod autoplay;
+pub mod cache;
 pub mod commands;
+pub mod crossfade;
+pub mod effects;
+pub mod error;
 pub mod events;
+pub mod favourites;
+pub mod history;
+pub mod library;
+pub mod live;
+pub mod loudness;
+pub mod mix;
+pub mod opus;
+pub mod playlists;
+pub mod prefetch;
+pub mod progress;
+pub mod radio;
+pub mod selfcheck;
+pub mod settings;
+pub mod store;
+pub mod track;
 pub mod util;
+pub mod ytdlp;
 
 struct Handler;
 
@@ -18,31 +39,12 @@ struct Handler;
 impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction {
-            Interaction::ApplicationCommand(command) => match command.data.name.as_str() {
-                "join" => commands::join::run(&ctx, &command).await,
-                "leave" => commands::leave::run(&ctx, &command).await,
-                "mute" => commands::mute::run(&ctx, &command).await,
-                "ping" => commands::ping::run(&ctx, &command).await,
-                "queue" => commands::queue::run(&ctx, &command).await,
-                "skip" => commands::skip::run(&ctx, &command).await,
-                "stop" => commands::stop::run(&ctx, &command).await,
-                "unmute" => commands::unmute::run(&ctx, &command).await,
-                "search" => commands::search::run(&ctx, &command).await,
-                "setrain" => commands::setrain::run(&ctx, &command).await,
-                _ => {
-                    match crate::util::respond_to_interaction(
-                        &command,
-                        &ctx.http,
-                        true,
-                        "Unknown command",
-                    )
-                    .await
-                    {
-                        Some(_) => (),
-                        None => eprintln!("Failed to respond to unknown interaction"),
-                    };
-                }
-            },
+            Interaction::ApplicationCommand(command) => {
+                commands::REGISTRY.dispatch(&ctx, &command).await
+            }
+            Interaction::MessageComponent(component) => {
+                commands::dispatch_component(&ctx, &component).await
+            }
             _ => (),
         }
     }
@@ -52,42 +54,23 @@ impl EventHandler for Handler {
     }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
-        println!("{} is connected!", ready.user.name);
-
-        let guilds = ctx.cache.guilds();
-        let mut rain_enabled_map = match crate::util::RAIN_ENABLED.try_lock() {
-            Ok(map) => map,
-            Err(e) => {
-                eprintln!("Failed to lock RAIN_ENABLED map with error {}", e);
-                return;
-            }
-        };
-
-        for guild_id in guilds {
-            match guild_id
-                .set_application_commands(&ctx.http, |commands| {
-                    commands
-                        .create_application_command(|command| commands::join::register(command))
-                        .create_application_command(|command| commands::leave::register(command))
-                        .create_application_command(|command| commands::mute::register(command))
-                        .create_application_command(|command| commands::ping::register(command))
-                        .create_application_command(|command| commands::queue::register(command))
-                        .create_application_command(|command| commands::skip::register(command))
-                        .create_application_command(|command| commands::stop::register(command))
-                        .create_application_command(|command| commands::unmute::register(command))
-                        .create_application_command(|command| commands::search::register(command))
-                        .create_application_command(|command| commands::setrain::register(command))
-                })
-                .await
-            {
-                Ok(_) => println!("Registered slash commands for guild {}", guild_id),
-                Err(why) => eprintln!(
-                    "Failed to register slash commands for guild {}: {}",
-                    guild_id, why
-                ),
-            }
-            rain_enabled_map.insert(guild_id.to_string(), true);
+        tracing::info!(user = %ready.user.name, "connected");
+
+        commands::sync(&ctx, ready.application.id).await;
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild, is_new: bool) {
+        if is_new {
+            tracing::info!(guild_id = %guild.id, "joined guild");
         }
+
+        crate::util::RAIN_ENABLED
+            .lock()
+            .await
+            .entry(guild.id.to_string())
+            .or_insert(true);
+
+        commands::clear_guild_commands(&ctx, guild.id).await;
     }
 }
 
@@ -100,6 +83,13 @@ async fn main() {
         Err(e) => eprintln!("Failed to open .env: {}", e),
     }
 
+    if !crate::selfcheck::run().await {
+        eprintln!("Refusing to start until the failed checks are fixed");
+        std::process::exit(1);
+    }
+
+    crate::library::index_in_background();
+
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
@@ -0,0 +1,239 @@
+/// ffmpeg arguments that encode a mix, which go right before its output path.
+///
+/// Opus is what gets sent to Discord, so the mix can be played without being
+/// encoded again.
+pub const OUTPUT_ARGS: &[&str] = &["-c:a", "libopus", "-b:a", "128k", "-ar", "48000"];
+
+/// How long the rain takes to fade out at the end of a track, in seconds.
+const RAIN_FADE_SECS: f64 = 4.0;
+
+/// ffmpeg arguments, up to the output, that mix the rain at `rain_path` under
+/// the song at `song_path`.
+///
+/// The arguments are passed to ffmpeg as they are, without a shell, so the
+/// paths can contain anything a file name can.
+///
+/// The song is the first input and sets the length of the mix, with the rain
+/// looped for as long as it lasts. Neither is scaled down by the mix, so the
+/// gains are the levels they end up at. If `fade_out` is given, the length of
+/// the song once its effects are applied, the rain fades out over the last few
+/// seconds. Otherwise it plays at full level to the end, so that it carries
+/// straight on into the next track.
+pub fn rain_mix_args(
+    song_path: &str,
+    rain_path: &str,
+    song_gain: f64,
+    rain_gain: f64,
+    effects: Option<&str>,
+    fade_out: Option<f64>,
+) -> Vec<String> {
+    let song_effects = effects
+        .map(|filter| format!(",{}", filter))
+        .unwrap_or_default();
+    let rain_fade = fade_out
+        .filter(|duration| *duration > RAIN_FADE_SECS * 2.0)
+        .map(|duration| {
+            format!(
+                ",afade=t=out:st={:.3}:d={}",
+                duration - RAIN_FADE_SECS,
+                RAIN_FADE_SECS
+            )
+        })
+        .unwrap_or_default();
+
+    let filter = format!(
+        "[0:a]volume={:.2}dB{},aformat=channel_layouts=stereo[song];[1:a]volume={:.2}dB{},aformat=channel_layouts=stereo[rain];[song][rain]amix=inputs=2:duration=first:dropout_transition=0:normalize=0[a]",
+        song_gain, song_effects, rain_gain, rain_fade
+    );
+    [
+        "-i",
+        song_path,
+        "-stream_loop",
+        "-1",
+        "-i",
+        rain_path,
+        "-filter_complex",
+        &filter,
+        "-map",
+        "[a]",
+        "-ac",
+        "2",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+/// ffmpeg arguments, up to the output, that apply `effects` to the song at
+/// `song_path` without any rain.
+pub fn effects_args(song_path: &str, effects: &str) -> Vec<String> {
+    ["-i", song_path, "-af", effects]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::{Effects, Speed};
+    use std::ffi::OsStr;
+    use std::fmt::Debug;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    /// How far a mix may be off the song's length, in seconds. Opus works in
+    /// 20ms frames and has some padding at the start.
+    const TOLERANCE: f64 = 0.1;
+
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("quiet_night_bot-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create scratch dir");
+        dir
+    }
+
+    /// Whether ffmpeg and ffprobe can be run, noting that the test is skipped
+    /// if not.
+    fn has_ffmpeg() -> bool {
+        let found = ["ffmpeg", "ffprobe"].iter().all(|binary| {
+            Command::new(binary)
+                .arg("-version")
+                .output()
+                .map_or(false, |output| output.status.success())
+        });
+        if !found {
+            eprintln!("skipping: ffmpeg or ffprobe isn't on PATH");
+        }
+        found
+    }
+
+    fn run_ffmpeg<S: AsRef<OsStr> + Debug>(args: &[S]) {
+        let output = Command::new("ffmpeg")
+            .args(["-y", "-v", "error"])
+            .args(args)
+            .output()
+            .expect("failed to spawn ffmpeg");
+        assert!(
+            output.status.success(),
+            "ffmpeg {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    /// Generates `seconds` of `source`, an ffmpeg lavfi audio source.
+    fn generate(path: &Path, source: &str, seconds: f64) {
+        run_ffmpeg(&[
+            OsStr::new("-f"),
+            OsStr::new("lavfi"),
+            OsStr::new("-i"),
+            OsStr::new(source),
+            OsStr::new("-t"),
+            OsStr::new(&seconds.to_string()),
+            path.as_os_str(),
+        ]);
+    }
+
+    fn duration_of(path: &Path) -> f64 {
+        let output = Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-show_entries",
+                "format=duration",
+                "-of",
+                "default=noprint_wrappers=1:nokey=1",
+            ])
+            .arg(path)
+            .output()
+            .expect("failed to spawn ffprobe");
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .expect("ffprobe printed no duration")
+    }
+
+    /// Mixes `rain_secs` of noise under `song_secs` of a sine wave, and
+    /// returns the length of the mix.
+    fn mixed_length(test: &str, song_secs: f64, rain_secs: f64, effects: &Effects) -> f64 {
+        let dir = scratch_dir(test);
+        let song = dir.join("song.wav");
+        let rain = dir.join("rain.wav");
+        let mix = dir.join("mix.ogg");
+        generate(&song, "sine=frequency=440:sample_rate=48000", song_secs);
+        generate(&rain, "anoisesrc=color=pink:sample_rate=44100", rain_secs);
+
+        let filter = effects.filter();
+        let mut args = rain_mix_args(
+            &song.to_string_lossy(),
+            &rain.to_string_lossy(),
+            0.0,
+            -8.0,
+            filter.as_deref(),
+            Some(song_secs / effects.speed.factor()),
+        );
+        args.extend(OUTPUT_ARGS.iter().map(|arg| arg.to_string()));
+        args.push(mix.to_string_lossy().to_string());
+        run_ffmpeg(&args);
+
+        let length = duration_of(&mix);
+        let _ = std::fs::remove_dir_all(&dir);
+        length
+    }
+
+    #[test]
+    fn rain_only_fades_out_when_asked_to() {
+        let fades = |fade_out| {
+            rain_mix_args("song.opus", "rain.mp3", 0.0, -8.0, None, fade_out)
+                .iter()
+                .any(|arg| arg.contains("afade"))
+        };
+        assert!(!fades(None));
+        assert!(fades(Some(60.0)));
+        // Too short to fade without cutting into the middle of the track.
+        assert!(!fades(Some(5.0)));
+    }
+
+    #[test]
+    fn paths_are_passed_as_single_arguments() {
+        let song = "./library/Tom's \"Best\" $(rm -rf ~) `id`.flac";
+        let args = rain_mix_args(song, "/rain/rain & thunder.mp3", 0.0, -8.0, None, None);
+        assert_eq!(args[..2], ["-i", song]);
+        assert_eq!(args[4..6], ["-i", "/rain/rain & thunder.mp3"]);
+
+        let args = effects_args(song, "lowpass=f=800");
+        assert_eq!(args, ["-i", song, "-af", "lowpass=f=800"]);
+    }
+
+    #[test]
+    fn mix_lasts_as_long_as_the_song_when_the_rain_loops() {
+        if !has_ffmpeg() {
+            return;
+        }
+        let length = mixed_length("loop", 12.0, 1.5, &Effects::default());
+        assert!((length - 12.0).abs() < TOLERANCE, "mix lasted {}s", length);
+    }
+
+    #[test]
+    fn mix_lasts_as_long_as_the_song_when_the_rain_is_longer() {
+        if !has_ffmpeg() {
+            return;
+        }
+        let length = mixed_length("longer", 3.0, 10.0, &Effects::default());
+        assert!((length - 3.0).abs() < TOLERANCE, "mix lasted {}s", length);
+    }
+
+    #[test]
+    fn mix_follows_the_song_speed() {
+        if !has_ffmpeg() {
+            return;
+        }
+        let effects = Effects {
+            speed: Speed::Nightcore,
+            ..Effects::default()
+        };
+        let length = mixed_length("speed", 10.0, 2.0, &effects);
+        assert!((length - 8.0).abs() < TOLERANCE, "mix lasted {}s", length);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::track::TrackMetadata;
+use ogg::reading::PacketReader;
+use serenity::async_trait;
+use songbird::input::{
+    codec::OpusDecoderState, error::Error as InputError, error::Result as InputResult,
+    restartable::Restart, Codec, Container, Input, Metadata, Reader,
+};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::time::Duration;
+
+/// Opus always runs at 48kHz, whatever the source was recorded at.
+const SAMPLE_RATE: u32 = 48000;
+
+/// Cached Ogg Opus audio, handed to songbird as the Opus packets it already
+/// contains, so playing from the cache never goes through ffmpeg.
+///
+/// Songbird only passes packets straight through for a lone track at volume
+/// 1.0. Tracks are played at their loudness volume, so in practice it decodes
+/// the packets, applies the volume and encodes them again, much as it would
+/// ffmpeg's output.
+///
+/// The pre-skip and end trim of the Ogg stream aren't applied: the few
+/// milliseconds of encoder padding at either end are played as they are.
+pub struct OggOpus {
+    path: String,
+    metadata: Metadata,
+}
+
+impl OggOpus {
+    pub fn new(path: &str, track: &TrackMetadata) -> Self {
+        Self {
+            path: path.to_string(),
+            metadata: metadata(track),
+        }
+    }
+}
+
+/// What songbird is told about a track played from the cache.
+pub fn metadata(track: &TrackMetadata) -> Metadata {
+    Metadata {
+        title: Some(track.title.clone()),
+        channel: track.channel_name().map(str::to_string),
+        duration: track.duration(),
+        source_url: track.webpage_url.clone(),
+        thumbnail: track.thumbnail.clone(),
+        channels: Some(2),
+        sample_rate: Some(SAMPLE_RATE),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl Restart for OggOpus {
+    async fn call_restart(&mut self, time: Option<Duration>) -> InputResult<Input> {
+        let frames =
+            DcaFrames::open(&self.path, time.unwrap_or_default()).map_err(InputError::Io)?;
+
+        Ok(Input::new(
+            frames.channels > 1,
+            Reader::Extension(Box::new(frames)),
+            Codec::Opus(OpusDecoderState::new().map_err(InputError::Opus)?),
+            Container::Dca { first_frame: 0 },
+            Some(self.metadata.clone()),
+        ))
+    }
+
+    async fn lazy_init(&mut self) -> InputResult<(Option<Metadata>, Codec, Container)> {
+        Ok((
+            Some(self.metadata.clone()),
+            Codec::Opus(OpusDecoderState::new().map_err(InputError::Opus)?),
+            Container::Dca { first_frame: 0 },
+        ))
+    }
+}
+
+/// Reads the Opus packets out of an Ogg file as DCA frames, which are the
+/// packets each prefixed with their length as a little-endian `i16`.
+struct DcaFrames {
+    packets: PacketReader<BufReader<File>>,
+    channels: u8,
+    /// The frame being read out, and how much of it has been.
+    frame: Vec<u8>,
+    offset: usize,
+}
+
+impl DcaFrames {
+    /// Opens the file at `path`, skipping the headers and everything before
+    /// `start`.
+    fn open(path: &str, start: Duration) -> io::Result<Self> {
+        let mut packets = PacketReader::new(BufReader::new(File::open(path)?));
+
+        let head = next_packet(&mut packets)?.ok_or_else(|| invalid("empty Ogg file"))?;
+        if head.len() < 19 || !head.starts_with(b"OpusHead") {
+            return Err(invalid("not an Ogg Opus file"));
+        }
+        let channels = head[9];
+        let pre_skip = u64::from(u16::from_le_bytes([head[10], head[11]]));
+        // The comment header, which songbird has no use for.
+        next_packet(&mut packets)?;
+
+        let mut frames = Self {
+            packets,
+            channels,
+            frame: Vec::new(),
+            offset: 0,
+        };
+        if start > Duration::ZERO {
+            // Positions are only given per page, so this lands on the first
+            // page ending after `start`, which is close enough for seeking.
+            let target = pre_skip + (start.as_secs_f64() * f64::from(SAMPLE_RATE)) as u64;
+            while let Some(packet) = frames.packets.read_packet().map_err(ogg_error)? {
+                if packet.absgp_page() >= target {
+                    frames.set_frame(&packet.data);
+                    break;
+                }
+            }
+        }
+        Ok(frames)
+    }
+
+    fn set_frame(&mut self, packet: &[u8]) {
+        self.frame.clear();
+        self.frame
+            .extend_from_slice(&(packet.len() as i16).to_le_bytes());
+        self.frame.extend_from_slice(packet);
+        self.offset = 0;
+    }
+}
+
+impl Read for DcaFrames {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset >= self.frame.len() {
+            match next_packet(&mut self.packets)? {
+                // Empty packets are allowed in Ogg, but would read as the end
+                // of the stream in DCA.
+                Some(packet) if packet.is_empty() => continue,
+                Some(packet) => self.set_frame(&packet),
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.frame.len() - self.offset);
+        buf[..len].copy_from_slice(&self.frame[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+fn next_packet(packets: &mut PacketReader<BufReader<File>>) -> io::Result<Option<Vec<u8>>> {
+    Ok(packets
+        .read_packet()
+        .map_err(ogg_error)?
+        .map(|packet| packet.data))
+}
+
+fn ogg_error(e: ogg::OggReadError) -> io::Error {
+    match e {
+        ogg::OggReadError::ReadError(e) => e,
+        e => invalid(&e.to_string()),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
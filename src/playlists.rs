@@ -0,0 +1,102 @@
+use crate::error::{Error, Result};
+use crate::store::Store;
+use crate::track::SavedTrack;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use std::collections::{BTreeMap, HashMap};
+
+/// Most tracks a single playlist can hold.
+pub const MAX_TRACKS: usize = 200;
+
+lazy_static! {
+    /// Playlists by guild id, then by lowercased name.
+    static ref PLAYLISTS: Store<HashMap<String, BTreeMap<String, Playlist>>> =
+        Store::open("playlists");
+}
+
+/// A named list of tracks saved in a guild.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Playlist {
+    /// The name as it was given. Lookups ignore case.
+    pub name: String,
+    /// Who created the playlist, and so may change it.
+    pub owner: UserId,
+    pub tracks: Vec<SavedTrack>,
+}
+
+fn key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+pub async fn create(guild_id: GuildId, name: &str, owner: UserId) -> Result<()> {
+    PLAYLISTS
+        .update(|playlists| {
+            let playlists = playlists.entry(guild_id.to_string()).or_default();
+            if playlists.contains_key(&key(name)) {
+                return Err(Error::PlaylistExists(name.to_string()));
+            }
+            playlists.insert(
+                key(name),
+                Playlist {
+                    name: name.trim().to_string(),
+                    owner,
+                    tracks: Vec::new(),
+                },
+            );
+            Ok(())
+        })
+        .await?
+}
+
+pub async fn get(guild_id: GuildId, name: &str) -> Result<Playlist> {
+    PLAYLISTS
+        .read(|playlists| {
+            playlists
+                .get(&guild_id.to_string())
+                .and_then(|playlists| playlists.get(&key(name)))
+                .cloned()
+        })
+        .await?
+        .ok_or_else(|| Error::UnknownPlaylist(name.to_string()))
+}
+
+/// Every playlist of `guild_id`, sorted by name.
+pub async fn list(guild_id: GuildId) -> Result<Vec<Playlist>> {
+    PLAYLISTS
+        .read(|playlists| {
+            playlists
+                .get(&guild_id.to_string())
+                .map(|playlists| playlists.values().cloned().collect())
+                .unwrap_or_default()
+        })
+        .await
+}
+
+/// Applies `f` to the playlist called `name` and saves it.
+pub async fn update<R>(
+    guild_id: GuildId,
+    name: &str,
+    f: impl FnOnce(&mut Playlist) -> Result<R>,
+) -> Result<R> {
+    PLAYLISTS
+        .update(|playlists| {
+            let playlist = playlists
+                .get_mut(&guild_id.to_string())
+                .and_then(|playlists| playlists.get_mut(&key(name)))
+                .ok_or_else(|| Error::UnknownPlaylist(name.to_string()))?;
+            f(playlist)
+        })
+        .await?
+}
+
+pub async fn delete(guild_id: GuildId, name: &str) -> Result<Playlist> {
+    PLAYLISTS
+        .update(|playlists| {
+            playlists
+                .get_mut(&guild_id.to_string())
+                .and_then(|playlists| playlists.remove(&key(name)))
+                .ok_or_else(|| Error::UnknownPlaylist(name.to_string()))
+        })
+        .await?
+}
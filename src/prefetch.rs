@@ -0,0 +1,338 @@
+use crate::progress::Progress;
+use crate::settings::GuildSettings;
+use crate::track::{TrackMetadata, TrackSource};
+use lazy_static::lazy_static;
+use serenity::async_trait;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::{Context, Mutex};
+use songbird::input::{
+    codec::OpusDecoderState, error::Error as InputError, error::Result as InputResult,
+    restartable::Restart, Codec, Container, Input, Metadata,
+};
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+/// Output arguments for ffmpeg decoding a file that isn't cached as Opus.
+const FFMPEG_ARGS: [&str; 9] = [
+    "-f",
+    "f32le",
+    "-ac",
+    "2",
+    "-ar",
+    "48000",
+    "-acodec",
+    "pcm_f32le",
+    "-",
+];
+
+lazy_static! {
+    /// The job queue of each guild's worker.
+    static ref WORKERS: Mutex<HashMap<GuildId, mpsc::UnboundedSender<Job>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// How far along the audio of a queued track is.
+#[derive(Clone, Debug)]
+pub enum Status {
+    /// Waiting for the tracks queued before it.
+    Pending,
+    Downloading(Progress),
+    Measuring,
+    /// Mixing in the rain or effects.
+    Encoding(Progress),
+    /// Ready to play from the file at this path.
+    Ready(String),
+    /// Couldn't be fetched, for the reason given, which is meant for users.
+    Failed(String),
+}
+
+impl Status {
+    pub fn describe(&self) -> String {
+        let (stage, progress) = match self {
+            Status::Pending => return "Waiting to download".to_string(),
+            Status::Downloading(progress) => ("Downloading", progress),
+            Status::Measuring => return "Measuring loudness".to_string(),
+            Status::Encoding(progress) => ("Encoding", progress),
+            Status::Ready(_) => return "Ready".to_string(),
+            Status::Failed(reason) => return format!("Failed: {}", reason),
+        };
+        let progress = progress.describe();
+        if progress.is_empty() {
+            stage.to_string()
+        } else {
+            format!("{} {}", stage, progress)
+        }
+    }
+
+    /// Whether there's nothing more to wait for.
+    pub fn is_done(&self) -> bool {
+        matches!(self, Status::Ready(_) | Status::Failed(_))
+    }
+}
+
+/// Everything needed to download and mix a queued track.
+pub struct FetchRequest {
+    pub source: TrackSource,
+    /// The metadata as probed, before any effects were applied.
+    pub metadata: TrackMetadata,
+    /// Whether `metadata` came from the metadata index.
+    pub indexed: bool,
+    pub settings: GuildSettings,
+    pub rain_enabled: bool,
+}
+
+/// A fetch that hasn't been handed to the worker yet.
+pub struct Fetch {
+    request: FetchRequest,
+    status: watch::Sender<Status>,
+}
+
+impl Fetch {
+    pub fn subscribe(&self) -> watch::Receiver<Status> {
+        self.status.subscribe()
+    }
+}
+
+/// Sets up a fetch for `request`, returning it along with the source that
+/// plays its audio once it's done. `track` is the metadata to play it with.
+pub fn pending(request: FetchRequest, track: &TrackMetadata) -> (PendingAudio, Fetch) {
+    let (status, receiver) = watch::channel(Status::Pending);
+    let audio = PendingAudio {
+        status: receiver,
+        opus: crate::util::fetches_opus(&request),
+        track: track.clone(),
+    };
+    (audio, Fetch { request, status })
+}
+
+/// Hands `fetch` to the worker of `guild_id`, to be fetched once the tracks
+/// queued before it have been.
+///
+/// `track` is the queued track it's for. If it fails, the track is taken out
+/// of the queue and `notify` is told why.
+pub async fn submit(
+    ctx: &Context,
+    guild_id: GuildId,
+    fetch: Fetch,
+    track: TrackHandle,
+    notify: Option<ChannelId>,
+) {
+    let job = Job {
+        ctx: ctx.clone(),
+        guild_id,
+        fetch,
+        track,
+        notify,
+    };
+
+    let mut workers = WORKERS.lock().await;
+    let job = match workers.get(&guild_id) {
+        Some(worker) => match worker.send(job) {
+            Ok(()) => return,
+            Err(mpsc::error::SendError(job)) => job,
+        },
+        None => job,
+    };
+    // No worker yet, or it died; start a new one.
+    let worker = spawn_worker(guild_id);
+    let _ = worker.send(job);
+    workers.insert(guild_id, worker);
+}
+
+/// Waits until the audio for `status` is ready, returning why not if it
+/// couldn't be fetched.
+pub async fn wait_until_fetched(
+    status: &mut watch::Receiver<Status>,
+) -> std::result::Result<String, String> {
+    loop {
+        match &*status.borrow_and_update() {
+            Status::Ready(path) => return Ok(path.clone()),
+            Status::Failed(reason) => return Err(reason.clone()),
+            Status::Pending | Status::Downloading(_) | Status::Measuring | Status::Encoding(_) => {}
+        }
+        if status.changed().await.is_err() {
+            return Err("The download was cancelled".to_string());
+        }
+    }
+}
+
+fn spawn_worker(guild_id: GuildId) -> mpsc::UnboundedSender<Job> {
+    let (sender, mut jobs) = mpsc::unbounded_channel::<Job>();
+    tokio::spawn(async move {
+        while let Some(job) = jobs.recv().await {
+            job.run().await;
+        }
+        tracing::debug!(guild_id = %guild_id, "prefetch worker stopped");
+    });
+    sender
+}
+
+struct Job {
+    ctx: Context,
+    guild_id: GuildId,
+    fetch: Fetch,
+    track: TrackHandle,
+    notify: Option<ChannelId>,
+}
+
+impl Job {
+    async fn run(self) {
+        // Skipped, stopped or the bot left while it was waiting.
+        if self.track.get_info().await.is_err() {
+            self.fetch
+                .status
+                .send_replace(Status::Failed("The track was removed".to_string()));
+            return;
+        }
+
+        match crate::util::fetch_audio(&self.fetch.request, &self.fetch.status).await {
+            Ok((path, metadata)) => {
+                // The loudness may only just have been measured.
+                let volume = crate::loudness::track_volume(
+                    metadata.loudness,
+                    self.fetch.request.rain_enabled,
+                );
+                self.track
+                    .typemap()
+                    .write()
+                    .await
+                    .insert::<crate::loudness::VolumeKey>(volume);
+                let _ = self.track.set_volume(volume);
+
+                self.fetch.status.send_replace(Status::Ready(path));
+            }
+            Err(e) => {
+                tracing::warn!(
+                    guild_id = %self.guild_id,
+                    source = ?self.fetch.request.source,
+                    error = %e,
+                    "failed to fetch queued track"
+                );
+                self.fetch
+                    .status
+                    .send_replace(Status::Failed(e.user_message()));
+                self.remove_track().await;
+
+                if let Some(channel_id) = self.notify {
+                    let message = format!(
+                        "Couldn't play {}: {}",
+                        self.fetch.request.metadata.title,
+                        e.user_message()
+                    );
+                    if let Err(e) = channel_id.say(&self.ctx.http, message).await {
+                        tracing::warn!(guild_id = %self.guild_id, error = %e, "failed to report failed fetch");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the track out of the queue, so that it doesn't hold up the ones
+    /// after it.
+    async fn remove_track(&self) {
+        if let Ok(handler_lock) = crate::util::current_call(&self.ctx, self.guild_id).await {
+            if let Ok(handler) = crate::util::lock_call(&handler_lock).await {
+                let queue = handler.queue();
+                let uuid = self.track.uuid();
+                // The current track is dropped by the queue itself once it
+                // stops.
+                if queue
+                    .current()
+                    .map_or(true, |current| current.uuid() != uuid)
+                {
+                    queue.modify_queue(|tracks| {
+                        tracks.retain(|queued| queued.handle().uuid() != uuid)
+                    });
+                }
+            }
+        }
+        let _ = self.track.stop();
+    }
+}
+
+/// A queued track's audio, which plays once the worker has fetched it.
+///
+/// The track can be queued and shown right away, and only holds up playback
+/// if it comes up before its download is done.
+pub struct PendingAudio {
+    status: watch::Receiver<Status>,
+    /// Whether the audio ends up cached as Ogg Opus, which has to be known
+    /// before it is.
+    opus: bool,
+    track: TrackMetadata,
+}
+
+#[async_trait]
+impl Restart for PendingAudio {
+    async fn call_restart(&mut self, time: Option<Duration>) -> InputResult<Input> {
+        let path = wait_until_fetched(&mut self.status)
+            .await
+            .map_err(|reason| {
+                InputError::Io(std::io::Error::new(std::io::ErrorKind::Other, reason))
+            })?;
+
+        if self.opus {
+            crate::opus::OggOpus::new(&path, &self.track)
+                .call_restart(time)
+                .await
+        } else {
+            let start = format!("{:.3}", time.unwrap_or_default().as_secs_f64());
+            songbird::input::ffmpeg_optioned(&path, &["-ss", start.as_str()], &FFMPEG_ARGS).await
+        }
+    }
+
+    async fn lazy_init(&mut self) -> InputResult<(Option<Metadata>, Codec, Container)> {
+        let metadata = Some(crate::opus::metadata(&self.track));
+        if self.opus {
+            Ok((
+                metadata,
+                Codec::Opus(OpusDecoderState::new().map_err(InputError::Opus)?),
+                Container::Dca { first_frame: 0 },
+            ))
+        } else {
+            Ok((metadata, Codec::FloatPcm, Container::Raw))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_each_stage() {
+        assert_eq!(Status::Pending.describe(), "Waiting to download");
+        assert_eq!(
+            Status::Downloading(Progress::default()).describe(),
+            "Downloading"
+        );
+        assert_eq!(Status::Measuring.describe(), "Measuring loudness");
+        assert_eq!(
+            Status::Encoding(Progress {
+                fraction: Some(0.4),
+                speed: Some("3.0x".to_string()),
+                eta: Some(Duration::from_secs(5)),
+            })
+            .describe(),
+            "Encoding 40% at 3.0x, 0:05 left"
+        );
+        assert_eq!(
+            Status::Ready("./queue/a.ogg".to_string()).describe(),
+            "Ready"
+        );
+        assert_eq!(
+            Status::Failed(crate::ytdlp::Failure::Private.user_message().to_string()).describe(),
+            "Failed: This video is private"
+        );
+    }
+
+    #[test]
+    fn only_ready_and_failed_are_done() {
+        assert!(!Status::Pending.is_done());
+        assert!(!Status::Encoding(Progress::default()).is_done());
+        assert!(Status::Ready(String::new()).is_done());
+        assert!(Status::Failed(String::new()).is_done());
+    }
+}
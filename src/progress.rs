@@ -0,0 +1,290 @@
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+/// Makes yt-dlp print its progress as plain numbers, with `NA` for the ones
+/// it doesn't know.
+pub const YTDLP_TEMPLATE: &str = "download:[progress] %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s";
+
+/// How far a download or encode has got.
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    /// How much is done, from 0 to 1.
+    pub fraction: Option<f64>,
+    /// How fast it's going, ready to show.
+    pub speed: Option<String>,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Describes the progress to users, e.g. `45% at 2.1 MiB/s, 0:12 left`,
+    /// or an empty string if nothing is known yet.
+    pub fn describe(&self) -> String {
+        let mut description = self
+            .fraction
+            .map(|fraction| format!("{:.0}%", fraction * 100.0))
+            .unwrap_or_default();
+        if let Some(speed) = &self.speed {
+            description.push_str(&format!(" at {}", speed));
+        }
+        if let Some(eta) = self.eta {
+            description.push_str(&format!(", {} left", crate::track::format_duration(eta)));
+        }
+        description.trim_start_matches(&[' ', ','][..]).to_string()
+    }
+}
+
+/// Parses a progress line yt-dlp printed with [`YTDLP_TEMPLATE`].
+pub fn parse_ytdlp(line: &str) -> Option<Progress> {
+    let fields: Vec<Option<f64>> = line
+        .strip_prefix("[progress]")?
+        .split_whitespace()
+        .map(|field| field.parse().ok())
+        .collect();
+    match fields.as_slice() {
+        [downloaded, total, estimate, speed, eta] => Some(Progress {
+            fraction: downloaded
+                .zip(total.or(*estimate))
+                .filter(|(_, total)| *total > 0.0)
+                .map(|(downloaded, total)| (downloaded / total).min(1.0)),
+            speed: speed.map(format_rate),
+            eta: eta.map(Duration::from_secs_f64),
+        }),
+        _ => None,
+    }
+}
+
+fn format_rate(bytes_per_sec: f64) -> String {
+    const KIB: f64 = 1024.0;
+    const MIB: f64 = 1024.0 * 1024.0;
+    if bytes_per_sec >= MIB {
+        format!("{:.1} MiB/s", bytes_per_sec / MIB)
+    } else {
+        format!("{:.0} KiB/s", bytes_per_sec / KIB)
+    }
+}
+
+/// Follows what ffmpeg prints with `-progress`, which comes in blocks of
+/// `key=value` lines ending with a `progress` line.
+pub struct FfmpegProgress {
+    /// Length of the output, in seconds.
+    duration: Option<f64>,
+    /// How much of the output is done, in seconds.
+    done: f64,
+    /// Times real time.
+    speed: Option<f64>,
+}
+
+impl FfmpegProgress {
+    /// For an output `duration` long, if that's known.
+    pub fn new(duration: Option<Duration>) -> Self {
+        Self {
+            duration: duration.map(|duration| duration.as_secs_f64()),
+            done: 0.0,
+            speed: None,
+        }
+    }
+
+    /// Takes in a line, returning the progress once a block is complete.
+    pub fn parse(&mut self, line: &str) -> Option<Progress> {
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim();
+        match key.trim() {
+            "out_time_us" => {
+                if let Ok(micros) = value.parse::<f64>() {
+                    self.done = micros / 1_000_000.0;
+                }
+            }
+            "speed" => self.speed = value.trim_end_matches('x').parse().ok(),
+            "progress" => return Some(self.progress()),
+            _ => {}
+        }
+        None
+    }
+
+    fn progress(&self) -> Progress {
+        let remaining = self
+            .duration
+            .map(|duration| (duration - self.done).max(0.0));
+        Progress {
+            fraction: self
+                .duration
+                .filter(|duration| *duration > 0.0)
+                .map(|duration| (self.done / duration).clamp(0.0, 1.0)),
+            speed: self.speed.map(|speed| format!("{:.1}x", speed)),
+            eta: remaining
+                .zip(self.speed.filter(|speed| *speed > 0.0))
+                .map(|(remaining, speed)| Duration::from_secs_f64(remaining / speed)),
+        }
+    }
+}
+
+/// Runs `command` to completion, handing each line it prints to stdout to
+/// `on_line` as it comes.
+///
+/// The output has the stdout lines `on_line` didn't take, by returning
+/// `false`, and all of stderr.
+pub async fn run(
+    command: &mut Command,
+    mut on_line: impl FnMut(&str) -> bool + Send,
+) -> std::io::Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take().ok_or_else(|| missing_pipe("stdout"))?;
+    let stderr = child.stderr.take().ok_or_else(|| missing_pipe("stderr"))?;
+
+    // Both have to be drained at once, or a full pipe stalls the process.
+    let (stdout, stderr) = tokio::try_join!(
+        read_lines(stdout, &mut on_line),
+        read_lines(stderr, &mut |_: &str| false),
+    )?;
+    let status = child.wait().await?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Reads `pipe` line by line, returning the lines `on_line` didn't take.
+async fn read_lines(
+    pipe: impl AsyncRead + Unpin,
+    on_line: &mut (impl FnMut(&str) -> bool + Send),
+) -> std::io::Result<Vec<u8>> {
+    let mut kept = Vec::new();
+    let mut lines = BufReader::new(pipe).split(b'\n');
+    while let Some(line) = lines.next_segment().await? {
+        // Not everything ffmpeg prints about a file is valid UTF-8.
+        if !on_line(&String::from_utf8_lossy(&line)) {
+            kept.extend_from_slice(&line);
+            kept.push(b'\n');
+        }
+    }
+    Ok(kept)
+}
+
+fn missing_pipe(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!("child process has no {}", name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ffmpeg_block(progress: &mut FfmpegProgress, block: &str) -> Vec<Progress> {
+        block
+            .lines()
+            .filter_map(|line| progress.parse(line))
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_ytdlp_progress_line() {
+        let progress = parse_ytdlp("[progress] 1048576 4194304 NA 2202009.6 12").unwrap();
+        assert_eq!(progress.fraction, Some(0.25));
+        assert_eq!(progress.speed.as_deref(), Some("2.1 MiB/s"));
+        assert_eq!(progress.eta, Some(Duration::from_secs(12)));
+        assert_eq!(progress.describe(), "25% at 2.1 MiB/s, 0:12 left");
+    }
+
+    #[test]
+    fn falls_back_to_the_estimated_size() {
+        let progress = parse_ytdlp("[progress] 512000 NA 1024000.5 51200 NA").unwrap();
+        assert!((progress.fraction.unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(progress.speed.as_deref(), Some("50 KiB/s"));
+        assert_eq!(progress.eta, None);
+    }
+
+    #[test]
+    fn knows_nothing_when_ytdlp_prints_only_na() {
+        let progress = parse_ytdlp("[progress] NA NA NA NA NA").unwrap();
+        assert_eq!(progress.fraction, None);
+        assert_eq!(progress.speed, None);
+        assert_eq!(progress.eta, None);
+        assert_eq!(progress.describe(), "");
+    }
+
+    #[test]
+    fn never_goes_past_done_or_divides_by_zero() {
+        let over = parse_ytdlp("[progress] 2000 1000 NA NA NA").unwrap();
+        assert_eq!(over.fraction, Some(1.0));
+        let empty = parse_ytdlp("[progress] 0 0 NA NA NA").unwrap();
+        assert_eq!(empty.fraction, None);
+    }
+
+    #[test]
+    fn ignores_other_ytdlp_output() {
+        assert!(parse_ytdlp("[download] Destination: ./queue/norain_abc.webm").is_none());
+        assert!(parse_ytdlp("[progress] 1 2 3").is_none());
+        assert!(parse_ytdlp("").is_none());
+    }
+
+    #[test]
+    fn reports_ffmpeg_progress_once_a_block_ends() {
+        let mut progress = FfmpegProgress::new(Some(Duration::from_secs(10)));
+        let updates = ffmpeg_block(
+            &mut progress,
+            "bitrate= 128.0kbits/s\n\
+             total_size=80000\n\
+             out_time_us=5000000\n\
+             out_time=00:00:05.000000\n\
+             speed=2.5x\n\
+             progress=continue",
+        );
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].fraction, Some(0.5));
+        assert_eq!(updates[0].speed.as_deref(), Some("2.5x"));
+        assert_eq!(updates[0].eta, Some(Duration::from_secs(2)));
+
+        let updates = ffmpeg_block(
+            &mut progress,
+            "out_time_us=10000000\nspeed=2.6x\nprogress=end",
+        );
+        assert_eq!(updates[0].fraction, Some(1.0));
+        assert_eq!(updates[0].eta, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn keeps_going_through_ffmpeg_na_values() {
+        let mut progress = FfmpegProgress::new(Some(Duration::from_secs(10)));
+        let updates = ffmpeg_block(
+            &mut progress,
+            "out_time_us=N/A\nout_time=N/A\nspeed=N/A\nprogress=continue",
+        );
+        assert_eq!(updates[0].fraction, Some(0.0));
+        assert_eq!(updates[0].speed, None);
+        assert_eq!(updates[0].eta, None);
+
+        ffmpeg_block(
+            &mut progress,
+            "out_time_us=4000000\nspeed=1x\nprogress=continue",
+        );
+        // A value ffmpeg can't give this time keeps the last one.
+        let updates = ffmpeg_block(
+            &mut progress,
+            "out_time_us=N/A\nspeed=N/A\nprogress=continue",
+        );
+        assert_eq!(updates[0].fraction, Some(0.4));
+        assert_eq!(updates[0].speed, None);
+    }
+
+    #[test]
+    fn has_no_fraction_without_a_duration() {
+        let mut progress = FfmpegProgress::new(None);
+        let updates = ffmpeg_block(
+            &mut progress,
+            "out_time_us=3000000\nspeed=4x\nprogress=continue",
+        );
+        assert_eq!(updates[0].fraction, None);
+        assert_eq!(updates[0].eta, None);
+        assert_eq!(updates[0].describe(), "at 4.0x");
+    }
+}
@@ -0,0 +1,271 @@
+use crate::error::{Error, Result};
+use lazy_static::lazy_static;
+use serenity::prelude::TypeMapKey;
+use songbird::tracks::{PlayMode, TrackHandle};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+/// How often the song title of a playing radio stream is refreshed.
+const TITLE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Stations offered as choices in `/radio`.
+pub const STATIONS: &[(&str, &str)] = &[
+    (
+        "SomaFM Drone Zone",
+        "https://ice1.somafm.com/dronezone-128-mp3",
+    ),
+    (
+        "SomaFM Groove Salad",
+        "https://ice1.somafm.com/groovesalad-128-mp3",
+    ),
+    (
+        "SomaFM Deep Space One",
+        "https://ice1.somafm.com/deepspaceone-128-mp3",
+    ),
+    ("SomaFM Fluid", "https://ice1.somafm.com/fluid-128-mp3"),
+];
+
+/// The largest `icy-metaint` accepted. Real streams use 8 to 32 KiB; anything
+/// bigger is more audio than is worth reading for a title.
+const MAX_METAINT: usize = 64 * 1024;
+
+/// How long reading a stream's title may take in all, so that a stream that
+/// stalls doesn't hold up the title watcher.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+lazy_static! {
+    // Redirects aren't followed, as they could lead anywhere after the URL
+    // itself was checked.
+    static ref HTTP: reqwest::Client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Failed to build radio HTTP client");
+}
+
+/// The song currently playing on a radio track, from its ICY metadata.
+pub struct StreamTitleKey;
+
+impl TypeMapKey for StreamTitleKey {
+    type Value = String;
+}
+
+pub fn station_url(name: &str) -> Option<&'static str> {
+    STATIONS
+        .iter()
+        .find(|(station, _)| *station == name)
+        .map(|(_, url)| *url)
+}
+
+/// Fails unless every address the host of `url` resolves to is on the public
+/// internet, so that streams can't be used to reach the bot's own machine or
+/// network.
+pub async fn check_public(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|_| Error::InvalidOption("url"))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or(Error::InvalidOption("url"))?;
+    let host = parsed.host_str().ok_or(Error::InvalidOption("url"))?;
+    // IPv6 hosts keep their brackets.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<IpAddr> = match host.parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| Error::TrackRejected(format!("Couldn't find the host `{}`", host)))?
+            .map(|address| address.ip())
+            .collect(),
+    };
+    if addresses.is_empty() || !addresses.into_iter().all(is_public) {
+        return Err(Error::TrackRejected(
+            "That stream isn't on the public internet".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network" and carrier-grade NAT.
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7.
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10.
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Keeps the [`StreamTitleKey`] of `track` up to date until it finishes.
+pub fn watch_stream_title(track: TrackHandle, url: String) {
+    tokio::spawn(async move {
+        loop {
+            match track.get_info().await {
+                Ok(state) if matches!(state.playing, PlayMode::Play) => {
+                    if let Some(title) = fetch_stream_title(&url).await {
+                        track
+                            .typemap()
+                            .write()
+                            .await
+                            .insert::<StreamTitleKey>(title);
+                    }
+                }
+                Ok(state) if matches!(state.playing, PlayMode::Stop | PlayMode::End) => break,
+                Ok(_) => (),
+                Err(_) => break,
+            }
+            tokio::time::sleep(TITLE_POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// Reads the first ICY metadata block of an Icecast/Shoutcast stream.
+///
+/// Returns `None` for streams that don't send ICY metadata, like HLS.
+async fn fetch_stream_title(url: &str) -> Option<String> {
+    // Checked again on every poll, as the host may not resolve to the same
+    // addresses as when the stream was queued.
+    check_public(url).await.ok()?;
+    let mut response = HTTP
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .await
+        .ok()?;
+    let metaint = parse_metaint(response.headers().get("icy-metaint")?.to_str().ok()?)?;
+
+    // The metadata block comes after `metaint` bytes of audio, prefixed by
+    // its length in 16 byte units.
+    let mut buffer = Vec::new();
+    while buffer.len() <= metaint {
+        buffer.extend_from_slice(&response.chunk().await.ok()??);
+    }
+    let start = metaint.checked_add(1)?;
+    let end = start.checked_add(usize::from(buffer[metaint]).checked_mul(16)?)?;
+    while buffer.len() < end {
+        buffer.extend_from_slice(&response.chunk().await.ok()??);
+    }
+
+    parse_stream_title(&String::from_utf8_lossy(&buffer[start..end]))
+}
+
+/// Parses an `icy-metaint` header, which the server is free to make up.
+fn parse_metaint(header: &str) -> Option<usize> {
+    header
+        .trim()
+        .parse()
+        .ok()
+        .filter(|metaint| (1..=MAX_METAINT).contains(metaint))
+}
+
+fn parse_stream_title(metadata: &str) -> Option<String> {
+    const PREFIX: &str = "StreamTitle='";
+    let start = metadata.find(PREFIX)? + PREFIX.len();
+    let end = start + metadata[start..].find("';")?;
+    let title = metadata[start..end].trim();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn parses_the_stream_title() {
+        assert_eq!(
+            parse_stream_title("StreamTitle='Boards of Canada - Dayvan Cowboy';StreamUrl='';"),
+            Some("Boards of Canada - Dayvan Cowboy".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_quotes_inside_the_title() {
+        assert_eq!(
+            parse_stream_title("StreamTitle='Guns N' Roses - Don't Cry';"),
+            Some("Guns N' Roses - Don't Cry".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_padding_after_the_title() {
+        assert_eq!(
+            parse_stream_title("StreamTitle=' Tycho - Awake ';\0\0\0\0"),
+            Some("Tycho - Awake".to_string())
+        );
+    }
+
+    #[test]
+    fn has_no_title_when_it_is_empty_or_missing() {
+        assert_eq!(parse_stream_title("StreamTitle='';"), None);
+        assert_eq!(parse_stream_title("StreamUrl='https://example.com';"), None);
+        assert_eq!(parse_stream_title("StreamTitle='never terminated"), None);
+        assert_eq!(parse_stream_title(""), None);
+    }
+
+    #[test]
+    fn accepts_usual_metaints() {
+        assert_eq!(parse_metaint("16000"), Some(16000));
+        assert_eq!(parse_metaint(" 8192 "), Some(8192));
+    }
+
+    #[test]
+    fn rejects_metaints_that_are_zero_huge_or_malformed() {
+        assert_eq!(parse_metaint("0"), None);
+        assert_eq!(parse_metaint("1000000000000"), None);
+        assert_eq!(parse_metaint(&usize::MAX.to_string()), None);
+        assert_eq!(parse_metaint("-1"), None);
+        assert_eq!(parse_metaint("lots"), None);
+    }
+}
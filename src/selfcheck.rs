@@ -0,0 +1,258 @@
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use std::process::Command;
+use std::sync::RwLock;
+
+/// A video that has been on YouTube since the start, looked up to check the
+/// API key.
+const KEY_CHECK_VIDEO_ID: &str = "jNQXAC9IVRw";
+
+lazy_static! {
+    /// Features turned off because what they need failed its startup check.
+    static ref DISABLED: RwLock<HashSet<Feature>> = RwLock::new(HashSet::new());
+}
+
+/// Something the bot can do without, if what it needs isn't set up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Playing from YouTube, which needs yt-dlp.
+    YouTube,
+    /// Searching YouTube, which needs a valid API key.
+    Search,
+    /// Mixing in the ambience from `RAIN_PATH`.
+    Rain,
+}
+
+impl Feature {
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            Feature::YouTube => "Playing from YouTube isn't available on this bot right now",
+            Feature::Search => "Searching YouTube isn't available on this bot right now",
+            Feature::Rain => "The rain isn't available on this bot right now",
+        }
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feature::YouTube => write!(f, "YouTube playback"),
+            Feature::Search => write!(f, "YouTube search and related autoplay picks"),
+            Feature::Rain => write!(f, "rain"),
+        }
+    }
+}
+
+/// Whether `feature` passed its startup check.
+pub fn is_enabled(feature: Feature) -> bool {
+    DISABLED
+        .read()
+        .map_or(true, |disabled| !disabled.contains(&feature))
+}
+
+/// Fails with [`crate::error::Error::Unavailable`] if `feature` is turned off.
+pub fn require(feature: Feature) -> crate::error::Result<()> {
+    if is_enabled(feature) {
+        Ok(())
+    } else {
+        Err(crate::error::Error::Unavailable(feature))
+    }
+}
+
+/// What happens if a check fails.
+#[derive(Clone, Copy)]
+enum Severity {
+    /// The bot can't do anything useful, so it doesn't start.
+    Fatal,
+    Disables(Feature),
+}
+
+enum Outcome {
+    /// Passed, with anything worth knowing, like a version.
+    Ok(String),
+    /// Couldn't be checked, but may well work.
+    Warning(String),
+    Failed(String),
+}
+
+struct Check {
+    name: &'static str,
+    severity: Severity,
+    outcome: Outcome,
+}
+
+/// Checks everything the bot relies on outside of itself, logging a summary.
+///
+/// Features whose check failed are turned off. Returns whether the bot can
+/// start at all.
+pub async fn run() -> bool {
+    let ffmpeg = version_of("ffmpeg");
+    let ambience = if matches!(ffmpeg, Outcome::Ok(_)) {
+        check_ambience()
+    } else {
+        Outcome::Warning("skipped, ffmpeg is missing".to_string())
+    };
+
+    let checks = [
+        Check {
+            name: "yt-dlp config",
+            severity: Severity::Fatal,
+            outcome: match crate::ytdlp::validate() {
+                Ok(()) => Outcome::Ok(String::new()),
+                Err(e) => Outcome::Failed(e.to_string()),
+            },
+        },
+        Check {
+            name: "yt-dlp",
+            severity: Severity::Disables(Feature::YouTube),
+            outcome: match crate::ytdlp::version() {
+                Ok(version) => Outcome::Ok(version),
+                Err(e) => Outcome::Failed(e.to_string()),
+            },
+        },
+        Check {
+            name: "ffmpeg",
+            severity: Severity::Fatal,
+            outcome: ffmpeg,
+        },
+        Check {
+            name: "cache directory",
+            severity: Severity::Fatal,
+            outcome: check_cache_dir(),
+        },
+        Check {
+            name: "ambience",
+            severity: Severity::Disables(Feature::Rain),
+            outcome: ambience,
+        },
+        Check {
+            name: "YouTube API key",
+            severity: Severity::Disables(Feature::Search),
+            outcome: check_api_key().await,
+        },
+    ];
+
+    let mut can_start = true;
+    for check in &checks {
+        match (&check.outcome, check.severity) {
+            (Outcome::Ok(detail), _) => {
+                tracing::info!(check = check.name, detail = %detail, "startup check passed")
+            }
+            (Outcome::Warning(reason), _) => {
+                tracing::warn!(check = check.name, reason = %reason, "startup check inconclusive")
+            }
+            (Outcome::Failed(reason), Severity::Fatal) => {
+                tracing::error!(check = check.name, reason = %reason, "startup check failed");
+                can_start = false;
+            }
+            (Outcome::Failed(reason), Severity::Disables(feature)) => {
+                tracing::error!(
+                    check = check.name,
+                    reason = %reason,
+                    disabled = %feature,
+                    "startup check failed, disabling feature"
+                );
+                if let Ok(mut disabled) = DISABLED.write() {
+                    disabled.insert(feature);
+                }
+            }
+        }
+    }
+    can_start
+}
+
+/// Runs `binary -version`, one of the ffmpeg tools, for the version it
+/// prints first, e.g. `ffmpeg version 6.1.1 Copyright ...`.
+fn version_of(binary: &str) -> Outcome {
+    let output = match Command::new(binary).arg("-version").output() {
+        Ok(output) => output,
+        Err(e) => return Outcome::Failed(format!("failed to run {}: {}", binary, e)),
+    };
+    if !output.status.success() {
+        return Outcome::Failed(format!(
+            "{} exited with {}: {}",
+            binary,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first_line = stdout.lines().next().unwrap_or_default();
+    let version = first_line.split_whitespace().nth(2).unwrap_or(first_line);
+    Outcome::Ok(version.to_string())
+}
+
+/// Decodes the start of the ambience, so that a missing or broken file shows
+/// up now rather than when the first track is mixed.
+fn check_ambience() -> Outcome {
+    let path = match crate::util::rain_path() {
+        Ok(path) => path,
+        Err(_) => return Outcome::Failed("RAIN_PATH is not set".to_string()),
+    };
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-t", "1", "-i", &path, "-f", "null", "-"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Outcome::Ok(path),
+        Ok(output) => Outcome::Failed(format!(
+            "failed to decode {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => Outcome::Failed(format!("failed to run ffmpeg: {}", e)),
+    }
+}
+
+/// Makes sure downloads and mixes can be written to the cache.
+fn check_cache_dir() -> Outcome {
+    let dir = crate::cache::QUEUE_DIR;
+    let probe = format!("{}/.write_check", dir);
+    let result = std::fs::create_dir_all(dir)
+        .and_then(|()| std::fs::write(&probe, b"ok"))
+        .and_then(|()| std::fs::remove_file(&probe));
+    match result {
+        Ok(()) => Outcome::Ok(dir.to_string()),
+        Err(e) => Outcome::Failed(format!("{} is not writable: {}", dir, e)),
+    }
+}
+
+/// Looks up a video with the API key. If YouTube can't be reached the key is
+/// given the benefit of the doubt, as the outage may well be over by the time
+/// anyone searches.
+async fn check_api_key() -> Outcome {
+    let key = match std::env::var("YOUTUBE_API_KEY") {
+        Ok(key) if !key.trim().is_empty() => key,
+        _ => return Outcome::Failed("YOUTUBE_API_KEY is not set".to_string()),
+    };
+
+    let response = reqwest::Client::new()
+        .get("https://www.googleapis.com/youtube/v3/videos")
+        .query(&[("part", "id"), ("id", KEY_CHECK_VIDEO_ID), ("key", &key)])
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return Outcome::Warning(format!("failed to reach YouTube: {}", e)),
+    };
+
+    let status = response.status();
+    if status.is_success() {
+        return Outcome::Ok(String::new());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    // Running out of quota says nothing about the key, and passes by the
+    // next day.
+    if status.is_server_error() || message.to_lowercase().contains("quota") {
+        Outcome::Warning(format!("YouTube returned {}: {}", status, message))
+    } else {
+        Outcome::Failed(format!(
+            "YouTube rejected the key ({}): {}",
+            status, message
+        ))
+    }
+}
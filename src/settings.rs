@@ -0,0 +1,193 @@
+use crate::error::{Error, Result};
+use crate::store::Store;
+use crate::track::TrackMetadata;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+lazy_static! {
+    pub static ref GUILD_SETTINGS: Store<HashMap<String, GuildSettings>> =
+        Store::open("guild_settings");
+}
+
+/// Per-guild configuration, changed through slash commands.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Longest track that may be queued, in seconds.
+    pub max_duration_secs: u64,
+    /// Largest download that may be queued, in megabytes.
+    pub max_filesize_mb: u64,
+    pub allow_live: bool,
+    /// Whether to keep queuing tracks once the queue runs out.
+    pub autoplay: bool,
+    /// How long consecutive tracks overlap, fading one into the other. 0
+    /// cuts straight from one track to the next.
+    pub crossfade_secs: u64,
+    pub effects: crate::effects::Effects,
+    /// Whether the rain fades out at the end of each track, rather than
+    /// carrying on into the next one.
+    pub rain_fade: bool,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            max_duration_secs: 2 * 60 * 60,
+            max_filesize_mb: 200,
+            allow_live: false,
+            autoplay: false,
+            crossfade_secs: 0,
+            effects: crate::effects::Effects::default(),
+            rain_fade: false,
+        }
+    }
+}
+
+impl GuildSettings {
+    pub fn crossfade(&self) -> Duration {
+        Duration::from_secs(self.crossfade_secs)
+    }
+
+    /// Whether to fade the rain out at the end of each track. Never while
+    /// crossfading, where it would dip once for each of the two tracks.
+    pub fn fades_rain(&self) -> bool {
+        self.rain_fade && self.crossfade_secs == 0
+    }
+
+    /// Rejects tracks that break this guild's limits.
+    pub fn check_limits(&self, metadata: &TrackMetadata) -> Result<()> {
+        if metadata.is_live() && !self.allow_live {
+            return Err(Error::TrackRejected(
+                "Live streams aren't allowed in this server".to_string(),
+            ));
+        }
+
+        if let Some(duration) = metadata.duration() {
+            let max_duration = Duration::from_secs(self.max_duration_secs);
+            if duration > max_duration {
+                return Err(Error::TrackRejected(format!(
+                    "This track is {} long, over this server's limit of {}",
+                    crate::track::format_duration(duration),
+                    crate::track::format_duration(max_duration)
+                )));
+            }
+        }
+
+        if let Some(filesize) = metadata.filesize() {
+            let filesize_mb = filesize / (1024 * 1024);
+            if filesize_mb > self.max_filesize_mb {
+                return Err(Error::TrackRejected(format!(
+                    "This track is about {} MB, over this server's limit of {} MB",
+                    filesize_mb, self.max_filesize_mb
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn guild_settings(guild_id: GuildId) -> Result<GuildSettings> {
+    GUILD_SETTINGS
+        .read(|settings| {
+            settings
+                .get(&guild_id.to_string())
+                .cloned()
+                .unwrap_or_default()
+        })
+        .await
+}
+
+/// Applies `f` to the settings of `guild_id` and saves them.
+pub async fn update_guild_settings<R>(
+    guild_id: GuildId,
+    f: impl FnOnce(&mut GuildSettings) -> R,
+) -> Result<R> {
+    GUILD_SETTINGS
+        .update(|settings| f(settings.entry(guild_id.to_string()).or_default()))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: f64 = 1024.0 * 1024.0;
+
+    fn settings() -> GuildSettings {
+        GuildSettings {
+            max_duration_secs: 600,
+            max_filesize_mb: 50,
+            ..GuildSettings::default()
+        }
+    }
+
+    fn track(duration: Option<f64>) -> TrackMetadata {
+        TrackMetadata {
+            is_live: Some(false),
+            duration,
+            ..TrackMetadata::radio("Track", "https://example.com/track")
+        }
+    }
+
+    fn is_rejected(result: Result<()>) -> bool {
+        matches!(result, Err(Error::TrackRejected(_)))
+    }
+
+    #[test]
+    fn rejects_tracks_over_the_duration_limit() {
+        assert!(settings().check_limits(&track(Some(599.0))).is_ok());
+        assert!(settings().check_limits(&track(Some(600.0))).is_ok());
+        assert!(is_rejected(settings().check_limits(&track(Some(601.0)))));
+    }
+
+    #[test]
+    fn lets_tracks_of_unknown_length_through() {
+        assert!(settings().check_limits(&track(None)).is_ok());
+    }
+
+    #[test]
+    fn rejects_live_streams_unless_allowed() {
+        let live = TrackMetadata {
+            is_live: Some(true),
+            ..track(None)
+        };
+        assert!(is_rejected(settings().check_limits(&live)));
+
+        let settings = GuildSettings {
+            allow_live: true,
+            ..settings()
+        };
+        assert!(settings.check_limits(&live).is_ok());
+    }
+
+    #[test]
+    fn checks_the_exact_filesize_before_the_approximate_one() {
+        let sized = |filesize, filesize_approx| TrackMetadata {
+            filesize,
+            filesize_approx,
+            ..track(Some(60.0))
+        };
+        assert!(settings()
+            .check_limits(&sized(Some(40.0 * MB), None))
+            .is_ok());
+        assert!(is_rejected(
+            settings().check_limits(&sized(Some(60.0 * MB), None))
+        ));
+        // Only the approximate size is known.
+        assert!(settings()
+            .check_limits(&sized(None, Some(40.0 * MB)))
+            .is_ok());
+        assert!(is_rejected(
+            settings().check_limits(&sized(None, Some(60.0 * MB)))
+        ));
+        // The exact size wins over an estimate that's over the limit.
+        assert!(settings()
+            .check_limits(&sized(Some(40.0 * MB), Some(60.0 * MB)))
+            .is_ok());
+        assert!(settings().check_limits(&sized(None, None)).is_ok());
+    }
+}
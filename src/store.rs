@@ -0,0 +1,97 @@
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serenity::prelude::Mutex;
+use std::path::PathBuf;
+use tokio::time::timeout;
+
+/// Where everything that should survive a restart is kept.
+pub const DATA_DIR: &str = "./data";
+
+/// A value persisted as a JSON file in [`DATA_DIR`].
+///
+/// The whole value is rewritten on every update, which is fine for the small
+/// amounts of per-guild and per-user state the bot keeps. It's written to a
+/// temporary file first and renamed over the old one, so a crash mid-write
+/// leaves the previous version intact.
+pub struct Store<T> {
+    path: PathBuf,
+    value: Mutex<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default + Clone> Store<T> {
+    /// Loads `name.json`, starting from the default value if it doesn't exist
+    /// or can't be parsed. A file that can't be parsed is moved aside to
+    /// `name.json.corrupt` rather than overwritten by the next update.
+    pub fn open(name: &str) -> Self {
+        Self::open_at(PathBuf::from(DATA_DIR).join(format!("{}.json", name)))
+    }
+
+    /// Like [`Store::open`], for a file outside of [`DATA_DIR`].
+    pub fn open_at(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let value = match std::fs::read(&path) {
+            Ok(json) => match serde_json::from_slice(&json) {
+                Ok(value) => value,
+                Err(e) => {
+                    let aside = path.with_extension("json.corrupt");
+                    tracing::warn!(
+                        path = %path.display(),
+                        moved_to = %aside.display(),
+                        error = %e,
+                        "failed to parse store, starting over"
+                    );
+                    if let Err(e) = std::fs::rename(&path, &aside) {
+                        tracing::warn!(path = %path.display(), error = %e, "failed to move corrupt store aside");
+                    }
+                    T::default()
+                }
+            },
+            Err(_) => T::default(),
+        };
+
+        Self {
+            path,
+            value: Mutex::new(value),
+        }
+    }
+
+    pub async fn read<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R> {
+        let value = timeout(std::time::Duration::from_secs(5), self.value.lock())
+            .await
+            .map_err(|_| Error::LockTimeout)?;
+        Ok(f(&value))
+    }
+
+    /// Applies `f` to a copy of the value and writes it to disk, keeping the
+    /// change only once it's been written.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R> {
+        let mut value = timeout(std::time::Duration::from_secs(5), self.value.lock())
+            .await
+            .map_err(|_| Error::LockTimeout)?;
+        let mut updated = value.clone();
+        let result = f(&mut updated);
+
+        let json = serde_json::to_vec(&updated).map_err(|e| {
+            Error::Storage(format!(
+                "failed to serialize {}: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        let temp_path = self.path.with_extension("json.tmp");
+        let write = async {
+            if let Some(dir) = self.path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            tokio::fs::write(&temp_path, json).await?;
+            tokio::fs::rename(&temp_path, &self.path).await
+        };
+        write.await.map_err(|e| {
+            Error::Storage(format!("failed to write {}: {}", self.path.display(), e))
+        })?;
+
+        *value = updated;
+        Ok(result)
+    }
+}
@@ -0,0 +1,266 @@
+use crate::error::{Error, Result};
+use crate::store::Store;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::prelude::TypeMapKey;
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where the metadata of every cached track is kept, keyed by video id.
+const METADATA_INDEX_PATH: &str = "./queue/metadata.json";
+
+lazy_static! {
+    static ref METADATA_INDEX: Store<HashMap<String, TrackMetadata>> =
+        Store::open_at(METADATA_INDEX_PATH);
+}
+
+/// What we know about a track beyond its URL, as reported by yt-dlp.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TrackMetadata {
+    pub id: String,
+    pub title: String,
+    pub channel: Option<String>,
+    pub uploader: Option<String>,
+    /// Length in seconds. Missing for live streams.
+    pub duration: Option<f64>,
+    pub thumbnail: Option<String>,
+    /// Missing for local files.
+    pub webpage_url: Option<String>,
+    pub chapters: Option<Vec<Chapter>>,
+    pub is_live: Option<bool>,
+    /// Exact size of the audio download in bytes, when known up front.
+    pub filesize: Option<f64>,
+    pub filesize_approx: Option<f64>,
+    /// Measured once the audio is cached.
+    pub loudness: Option<crate::loudness::Loudness>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+/// Where a track comes from, in a form that can be saved and queued again
+/// later.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TrackSource {
+    /// A YouTube video or live stream, by video id.
+    YouTube {
+        id: String,
+    },
+    /// A file in the local library, by its path relative to the library root.
+    Local {
+        path: String,
+    },
+    Radio {
+        name: String,
+        url: String,
+    },
+}
+
+impl TrackSource {
+    /// Parses what a user typed to name a track: a YouTube URL,
+    /// `local:<path>`, the name of a built-in station or a stream URL.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Some(captures) = crate::util::YOUTUBE_URL_REGEX.captures(input) {
+            let id = captures.name("video_id")?.as_str().to_string();
+            return Some(TrackSource::YouTube { id });
+        }
+        if let Some(path) = input.strip_prefix("local:") {
+            return Some(TrackSource::Local {
+                path: path.to_string(),
+            });
+        }
+        if let Some(url) = crate::radio::station_url(input) {
+            return Some(TrackSource::Radio {
+                name: input.to_string(),
+                url: url.to_string(),
+            });
+        }
+        if input.starts_with("http://") || input.starts_with("https://") {
+            return Some(TrackSource::Radio {
+                name: input.to_string(),
+                url: input.to_string(),
+            });
+        }
+        None
+    }
+}
+
+/// A track kept around to be queued again, with its title for display.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedTrack {
+    pub source: TrackSource,
+    pub title: String,
+}
+
+impl From<&TrackMetadata> for SavedTrack {
+    fn from(metadata: &TrackMetadata) -> Self {
+        Self {
+            source: metadata.source(),
+            title: metadata.title.clone(),
+        }
+    }
+}
+
+/// Attaches a track's [`TrackMetadata`] to its songbird handle.
+pub struct TrackMetadataKey;
+
+impl TypeMapKey for TrackMetadataKey {
+    type Value = Arc<TrackMetadata>;
+}
+
+impl TrackMetadata {
+    /// Metadata for a radio stream, which yt-dlp knows nothing about.
+    pub fn radio(name: &str, url: &str) -> Self {
+        Self {
+            id: url.to_string(),
+            title: name.to_string(),
+            channel: None,
+            uploader: None,
+            duration: None,
+            thumbnail: None,
+            webpage_url: Some(url.to_string()),
+            chapters: None,
+            is_live: Some(true),
+            filesize: None,
+            filesize_approx: None,
+            loudness: None,
+        }
+    }
+
+    /// Where the track came from, recovered from its id.
+    pub fn source(&self) -> TrackSource {
+        if let Some(path) = self.id.strip_prefix("local:") {
+            TrackSource::Local {
+                path: path.to_string(),
+            }
+        } else if self.id.starts_with("http://") || self.id.starts_with("https://") {
+            TrackSource::Radio {
+                name: self.title.clone(),
+                url: self.id.clone(),
+            }
+        } else {
+            TrackSource::YouTube {
+                id: self.id.clone(),
+            }
+        }
+    }
+
+    pub fn channel_name(&self) -> Option<&str> {
+        self.channel.as_deref().or(self.uploader.as_deref())
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration.map(Duration::from_secs_f64)
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.is_live.unwrap_or(false)
+    }
+
+    /// Size of the audio download in bytes, exact if yt-dlp knows it.
+    pub fn filesize(&self) -> Option<u64> {
+        self.filesize
+            .or(self.filesize_approx)
+            .map(|filesize| filesize as u64)
+    }
+
+    /// The metadata of the track played `factor` times as fast.
+    pub fn at_speed(mut self, factor: f64) -> Self {
+        if factor == 1.0 {
+            return self;
+        }
+        self.duration = self.duration.map(|duration| duration / factor);
+        for chapter in self.chapters.iter_mut().flatten() {
+            chapter.start_time /= factor;
+            chapter.end_time /= factor;
+        }
+        self
+    }
+
+    /// The chapter playing at `position`, if the track has chapters.
+    pub fn chapter_at(&self, position: Duration) -> Option<&Chapter> {
+        let position = position.as_secs_f64();
+        self.chapters
+            .as_ref()?
+            .iter()
+            .find(|chapter| chapter.start_time <= position && position < chapter.end_time)
+    }
+}
+
+/// The metadata attached to a queued track.
+pub async fn metadata_of(track: &TrackHandle) -> Option<Arc<TrackMetadata>> {
+    track
+        .typemap()
+        .read()
+        .await
+        .get::<TrackMetadataKey>()
+        .cloned()
+}
+
+pub fn parse_metadata(json: &[u8]) -> Result<TrackMetadata> {
+    serde_json::from_slice(json)
+        .map_err(|e| Error::DownloadFailed(format!("failed to parse yt-dlp metadata: {}", e)))
+}
+
+/// The stored metadata of a cached track.
+pub async fn cached_metadata(id: &str) -> Option<TrackMetadata> {
+    match METADATA_INDEX.read(|index| index.get(id).cloned()).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to read metadata index");
+            None
+        }
+    }
+}
+
+/// Adds `metadata` to the index kept beside the cached audio.
+pub async fn store_metadata(metadata: &TrackMetadata) {
+    let result = METADATA_INDEX
+        .update(|index| index.insert(metadata.id.clone(), metadata.clone()))
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "failed to write metadata index");
+    }
+}
+
+/// Looks up the metadata of `url` without downloading it.
+///
+/// Selects the same format as the download, so that the reported file size
+/// is that of the audio we would fetch.
+pub async fn fetch_metadata(url: &str) -> Result<TrackMetadata> {
+    let output = crate::ytdlp::with_retries(url, |format| async move {
+        let output = crate::ytdlp::command()
+            .args(["-f", format, "-J", "--skip-download", url])
+            .output()
+            .await
+            .map_err(|e| {
+                Error::DownloadFailed(format!("failed to execute yt-dlp process: {:?}", e))
+            })?;
+        if !output.status.success() {
+            return Err(crate::ytdlp::failure(&output));
+        }
+        Ok(output)
+    })
+    .await?;
+
+    parse_metadata(&output.stdout)
+}
+
+/// Formats a duration as `m:ss`, or `h:mm:ss` once it reaches an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
@@ -1,11 +1,22 @@
+use crate::error::{Error, Result};
+use crate::track::{SavedTrack, TrackMetadata, TrackSource};
 use lazy_static::lazy_static;
+use serenity::model::application::command::CommandOptionType;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::prelude::*;
 use serenity::{
+    builder::{CreateEmbed, EditInteractionResponse},
     http::client::Http,
-    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+    model::id::{ChannelId, GuildId, UserId},
+    model::prelude::interaction::application_command::{
+        ApplicationCommandInteraction, CommandDataOptionValue,
+    },
+    model::prelude::interaction::message_component::MessageComponentInteraction,
+    model::user::User,
 };
 use songbird::input::Restartable;
+use songbird::tracks::TrackHandle;
+use songbird::{Call, Event, Songbird, TrackEvent};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::time::timeout;
@@ -13,32 +24,35 @@ use yt_api::ApiKey;
 
 lazy_static! {
     pub static ref YOUTUBE_URL_REGEX: regex::Regex = regex::Regex::new(
-        r"^(?:https?://)?(?:www\.)?(?:youtu\.be/|youtube\.com/(?:embed/|v/|watch\?v=|watch\?.+&v=))(?P<video_id>[\w-]{11})(?:\S+)?$"
+        r"^(?:https?://)?(?:www\.)?(?:youtu\.be/|youtube\.com/(?:embed/|v/|live/|watch\?v=|watch\?.+&v=))(?P<video_id>[\w-]{11})(?:\S+)?$"
     ).expect("Failed to compile YouTube URL regex");
     pub static ref YOUTUBE_API_KEY: ApiKey = ApiKey::new(std::env::var("YOUTUBE_API_KEY").expect("YOUTUBE_API_KEY not set"));
     pub static ref RAIN_ENABLED: Arc<Mutex<HashMap<String, bool>>> = Arc::new(Mutex::new(HashMap::new()));
 }
 
+/// How long to wait on a lock before giving up with [`Error::LockTimeout`].
+const LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How many tracks that failed to queue are named in [`play_saved`]'s report.
+const MAX_LISTED_FAILURES: usize = 10;
+
+/// How often [`report_progress`] edits the response at most.
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
+
 pub async fn respond_to_interaction<S: ToString>(
     interaction: &ApplicationCommandInteraction,
     http: &Arc<Http>,
     ephemeral: bool,
     content: S,
-) -> Option<()> {
-    match interaction
+) -> Result<()> {
+    interaction
         .create_interaction_response(http, |create| {
             create.interaction_response_data(|data| {
                 data.content(content.to_string()).ephemeral(ephemeral)
             })
         })
-        .await
-    {
-        Ok(_) => return Some(()),
-        Err(e) => {
-            eprintln!("Cannot respond to slash command: {}", e);
-            return None;
-        }
-    };
+        .await?;
+    Ok(())
 }
 
 pub async fn follow_up_interaction<S: ToString>(
@@ -46,306 +60,1131 @@ pub async fn follow_up_interaction<S: ToString>(
     http: &Arc<Http>,
     ephemeral: bool,
     content: S,
-) -> Option<()> {
-    match interaction
+) -> Result<()> {
+    interaction
         .create_followup_message(http, |create| {
             create.content(content.to_string()).ephemeral(ephemeral)
         })
-        .await
-    {
-        Ok(_) => return Some(()),
-        Err(e) => {
-            eprintln!("Cannot respond to slash command: {}", e);
-            return None;
-        }
-    };
+        .await?;
+    Ok(())
 }
 
-pub async fn play_song(ctx: &Context, interaction: &ApplicationCommandInteraction, url: &str) {
-    let guild_id = match interaction.guild_id {
-        Some(id) => id,
-        None => {
-            match interaction
-                .create_interaction_response(&ctx.http, |r| {
-                    r.kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|m| {
-                            m.content("This command can only be used in a server")
-                        })
-                })
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    eprintln!("Cannot respond to slash command: {}", e);
-                    return;
+/// Acknowledges the interaction so the response can be filled in later with
+/// [`edit_response`], past Discord's 3 second deadline.
+pub async fn defer_interaction(
+    interaction: &ApplicationCommandInteraction,
+    http: &Arc<Http>,
+) -> Result<()> {
+    interaction
+        .create_interaction_response(http, |create| {
+            create.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        })
+        .await?;
+    Ok(())
+}
+
+/// Replaces the content of a deferred or already sent response.
+pub async fn edit_response<S: ToString>(
+    interaction: &ApplicationCommandInteraction,
+    http: &Arc<Http>,
+    content: S,
+) -> Result<()> {
+    interaction
+        .edit_original_interaction_response(http, |edit| edit.content(content.to_string()))
+        .await?;
+    Ok(())
+}
+
+/// Logs a failed command and tells the user what went wrong.
+///
+/// The error is only shown to the user. Deferred commands have a public
+/// response already, which is deleted in favour of an ephemeral follow-up.
+/// Otherwise the interaction is responded to if it hasn't been yet, with an
+/// ephemeral follow-up as the fallback.
+pub async fn report_error(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    deferred: bool,
+    error: &Error,
+) {
+    if error.is_user_error() {
+        tracing::info!(
+            command = %interaction.data.name,
+            guild_id = ?interaction.guild_id,
+            user_id = %interaction.user.id,
+            error = %error,
+            "command rejected"
+        );
+    } else {
+        tracing::error!(
+            command = %interaction.data.name,
+            guild_id = ?interaction.guild_id,
+            user_id = %interaction.user.id,
+            error = %error,
+            "command failed"
+        );
+    }
+
+    let message = error.user_message();
+    let reported = if deferred {
+        match follow_up_interaction(interaction, &ctx.http, true, &message).await {
+            Ok(()) => {
+                if let Err(e) = interaction
+                    .delete_original_interaction_response(&ctx.http)
+                    .await
+                {
+                    tracing::warn!(
+                        command = %interaction.data.name,
+                        error = %e,
+                        "failed to delete deferred response"
+                    );
                 }
+                Ok(())
             }
+            // Better shown to everyone than not at all.
+            Err(_) => edit_response(interaction, &ctx.http, &message).await,
+        }
+    } else {
+        match respond_to_interaction(interaction, &ctx.http, true, &message).await {
+            Ok(()) => Ok(()),
+            Err(_) => follow_up_interaction(interaction, &ctx.http, true, &message).await,
         }
     };
+    if let Err(e) = reported {
+        tracing::error!(
+            command = %interaction.data.name,
+            error = %e,
+            "failed to report command error to user"
+        );
+    }
+}
 
-    // regex for youtube video id
-    let re = &crate::util::YOUTUBE_URL_REGEX;
+/// Like [`report_error`], for a button press.
+pub async fn report_component_error(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    deferred: bool,
+    error: &Error,
+) {
+    if error.is_user_error() {
+        tracing::info!(
+            custom_id = %component.data.custom_id,
+            guild_id = ?component.guild_id,
+            user_id = %component.user.id,
+            error = %error,
+            "button press rejected"
+        );
+    } else {
+        tracing::error!(
+            custom_id = %component.data.custom_id,
+            guild_id = ?component.guild_id,
+            user_id = %component.user.id,
+            error = %error,
+            "button press failed"
+        );
+    }
 
-    if !re.is_match(&url) {
-        crate::util::respond_to_interaction(
-            interaction,
-            &ctx.http,
-            true,
-            "Must provide a valid YouTube URL",
-        )
-        .await;
+    let message = error.user_message();
+    let reported = if deferred {
+        component
+            .edit_original_interaction_response(&ctx.http, |edit| edit.content(&message))
+            .await
+    } else {
+        component
+            .create_interaction_response(&ctx.http, |create| {
+                create.interaction_response_data(|data| data.content(&message).ephemeral(true))
+            })
+            .await
+    };
+    if let Err(e) = reported {
+        tracing::error!(
+            custom_id = %component.data.custom_id,
+            error = %e,
+            "failed to report button error to user"
+        );
+    }
+}
 
-        return;
+pub fn guild_id(interaction: &ApplicationCommandInteraction) -> Result<GuildId> {
+    interaction.guild_id.ok_or(Error::NotInGuild)
+}
+
+pub async fn songbird_manager(ctx: &Context) -> Arc<Songbird> {
+    songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone()
+}
+
+/// Locks a voice call, giving up after [`LOCK_TIMEOUT`].
+pub async fn lock_call(
+    handler_lock: &Arc<Mutex<Call>>,
+) -> Result<tokio::sync::MutexGuard<'_, Call>> {
+    timeout(LOCK_TIMEOUT, handler_lock.lock())
+        .await
+        .map_err(|_| Error::LockTimeout)
+}
+
+/// Gets the call for `guild_id`, failing if the bot isn't in a voice channel.
+pub async fn current_call(ctx: &Context, guild_id: GuildId) -> Result<Arc<Mutex<Call>>> {
+    songbird_manager(ctx)
+        .await
+        .get(guild_id)
+        .ok_or(Error::NotConnected)
+}
+
+/// The track that is playing in `guild_id`.
+pub async fn current_track(ctx: &Context, guild_id: GuildId) -> Result<TrackHandle> {
+    let handler_lock = current_call(ctx, guild_id).await?;
+    let handler = lock_call(&handler_lock).await?;
+    handler.queue().current().ok_or(Error::NothingPlaying)
+}
+
+/// The voice channel `user_id` is currently connected to in `guild_id`.
+pub fn user_voice_channel(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Result<ChannelId> {
+    let guild = ctx.cache.guild(guild_id).ok_or(Error::GuildNotCached)?;
+    guild
+        .voice_states
+        .get(&user_id)
+        .and_then(|voice_state| voice_state.channel_id)
+        .ok_or(Error::NotInVoice)
+}
+
+/// A hash that stays the same across builds and platforms, for naming files.
+pub fn stable_hash(value: &str) -> u64 {
+    // 64-bit FNV-1a
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The ambience file mixed into tracks when rain is enabled.
+pub fn rain_path() -> Result<String> {
+    std::env::var("RAIN_PATH").map_err(|e| Error::Config(format!("failed to get RAIN_PATH: {}", e)))
+}
+
+pub async fn rain_enabled(guild_id: GuildId) -> Result<bool> {
+    // Turned off everywhere if the ambience failed its startup check.
+    if !crate::selfcheck::is_enabled(crate::selfcheck::Feature::Rain) {
+        return Ok(false);
+    }
+    let map = timeout(LOCK_TIMEOUT, RAIN_ENABLED.lock())
+        .await
+        .map_err(|_| Error::LockTimeout)?;
+    Ok(*map.get(&guild_id.to_string()).unwrap_or(&false))
+}
+
+/// The subcommand that was used, for commands that have them.
+pub fn subcommand(interaction: &ApplicationCommandInteraction) -> Result<&str> {
+    match interaction.data.options.first() {
+        Some(option) if option.kind == CommandOptionType::SubCommand => Ok(&option.name),
+        _ => Err(Error::UnknownCommand),
+    }
+}
+
+/// Looks up an option, inside the subcommand if one was used.
+fn option<'a>(
+    interaction: &'a ApplicationCommandInteraction,
+    name: &'static str,
+) -> Option<&'a CommandDataOptionValue> {
+    let options = match interaction.data.options.first() {
+        Some(option) if option.kind == CommandOptionType::SubCommand => &option.options,
+        _ => &interaction.data.options,
+    };
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.resolved.as_ref())
+}
+
+pub fn string_option<'a>(
+    interaction: &'a ApplicationCommandInteraction,
+    name: &'static str,
+) -> Result<&'a str> {
+    match option(interaction, name) {
+        Some(CommandDataOptionValue::String(value)) => Ok(value),
+        Some(_) => Err(Error::InvalidOption(name)),
+        None => Err(Error::MissingOption(name)),
     }
+}
+
+pub fn optional_string_option<'a>(
+    interaction: &'a ApplicationCommandInteraction,
+    name: &'static str,
+) -> Result<Option<&'a str>> {
+    match option(interaction, name) {
+        Some(CommandDataOptionValue::String(value)) => Ok(Some(value)),
+        Some(_) => Err(Error::InvalidOption(name)),
+        None => Ok(None),
+    }
+}
+
+pub fn optional_int_option(
+    interaction: &ApplicationCommandInteraction,
+    name: &'static str,
+) -> Result<Option<i64>> {
+    match option(interaction, name) {
+        Some(CommandDataOptionValue::Integer(value)) => Ok(Some(*value)),
+        Some(_) => Err(Error::InvalidOption(name)),
+        None => Ok(None),
+    }
+}
+
+pub fn optional_bool_option(
+    interaction: &ApplicationCommandInteraction,
+    name: &'static str,
+) -> Result<Option<bool>> {
+    match option(interaction, name) {
+        Some(CommandDataOptionValue::Boolean(value)) => Ok(Some(*value)),
+        Some(_) => Err(Error::InvalidOption(name)),
+        None => Ok(None),
+    }
+}
+
+pub fn optional_user_option<'a>(
+    interaction: &'a ApplicationCommandInteraction,
+    name: &'static str,
+) -> Result<Option<&'a User>> {
+    match option(interaction, name) {
+        Some(CommandDataOptionValue::User(user, _)) => Ok(Some(user)),
+        Some(_) => Err(Error::InvalidOption(name)),
+        None => Ok(None),
+    }
+}
+
+pub fn bool_option(
+    interaction: &ApplicationCommandInteraction,
+    name: &'static str,
+) -> Result<bool> {
+    match option(interaction, name) {
+        Some(CommandDataOptionValue::Boolean(value)) => Ok(*value),
+        Some(_) => Err(Error::InvalidOption(name)),
+        None => Err(Error::MissingOption(name)),
+    }
+}
+
+/// A track that has just been added to a call's queue.
+pub struct QueuedTrack {
+    pub handle: TrackHandle,
+    pub metadata: TrackMetadata,
+    /// Place in the queue, where 1 is the track that is playing.
+    pub position: usize,
+    /// How long until the track starts, or `None` if that isn't known.
+    pub wait: Option<std::time::Duration>,
+    pub rain_enabled: bool,
+    /// How the download of the track's audio is going, for tracks that are
+    /// fetched in the background.
+    pub status: Option<tokio::sync::watch::Receiver<crate::prefetch::Status>>,
+}
+
+/// Queues `url`, to be downloaded and mixed in the background.
+///
+/// The interaction must have been deferred; progress and the result are
+/// edited into the original response.
+pub async fn play_song(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    url: &str,
+) -> Result<()> {
+    // regex for youtube video id
+    let re = &crate::util::YOUTUBE_URL_REGEX;
 
     let video_id = re
-        .captures(&url)
-        .unwrap()
-        .name("video_id")
-        .unwrap()
+        .captures(url)
+        .and_then(|captures| captures.name("video_id"))
+        .ok_or(Error::InvalidUrl)?
         .as_str();
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    let handler_lock = match manager.get(guild_id) {
-        Some(handler_lock) => handler_lock,
-        None => {
-            let guild = match ctx.cache.guild(guild_id) {
-                Some(guild) => guild,
-                None => {
-                    crate::util::respond_to_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "Internal Error: Failed to get guild from cache",
-                    )
-                    .await;
-                    return;
-                }
-            };
-            let channel_id = guild
-                .voice_states
-                .get(&interaction.user.id)
-                .and_then(|voice_state| voice_state.channel_id);
-
-            let connect_to = match channel_id {
-                Some(channel) => channel,
-                None => {
-                    crate::util::respond_to_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "You must be in a voice channel to use this command",
-                    )
-                    .await;
-                    return;
-                }
-            };
-            let (handler_lock, success) = manager.join(guild_id, connect_to).await;
-            match success {
-                Ok(_) => handler_lock,
-                Err(e) => {
-                    eprintln!("Failed to join voice channel: {}", e);
-                    crate::util::respond_to_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "Failed to join voice channel",
-                    )
-                    .await;
-                    return;
+    let source = TrackSource::YouTube {
+        id: video_id.to_string(),
+    };
+    play_source(ctx, interaction, &source).await
+}
+
+/// Queues `source` for the user of `interaction`.
+///
+/// The interaction must have been deferred, like for [`play_song`].
+pub async fn play_source(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    source: &TrackSource,
+) -> Result<()> {
+    let guild_id = guild_id(interaction)?;
+    let queued = queue_source(
+        ctx,
+        guild_id,
+        interaction.user.id,
+        source,
+        Some(interaction),
+        Some(interaction.channel_id),
+    )
+    .await?;
+
+    interaction
+        .edit_original_interaction_response(&ctx.http, |edit| {
+            edit.content("")
+                .embed(|embed| queued_embed(embed, &interaction.user, &queued))
+        })
+        .await?;
+    report_progress(
+        ctx,
+        guild_id,
+        interaction.token.clone(),
+        interaction.user.clone(),
+        queued,
+    );
+    Ok(())
+}
+
+/// Keeps the response to the interaction with `token` showing how the
+/// download of `queued` is going, and once it's done, where the track ended
+/// up in the queue.
+pub fn report_progress(
+    ctx: &Context,
+    guild_id: GuildId,
+    token: String,
+    user: User,
+    mut queued: QueuedTrack,
+) {
+    let mut status = match queued.status.clone() {
+        Some(status) => status,
+        None => return,
+    };
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        while status.changed().await.is_ok() {
+            let done = status.borrow_and_update().is_done();
+            if done {
+                if let Err(e) = update_place(&ctx, guild_id, &mut queued).await {
+                    tracing::warn!(guild_id = %guild_id, error = %e, "failed to find queued track");
                 }
             }
+
+            let mut edit = EditInteractionResponse::default();
+            edit.embed(|embed| queued_embed(embed, &user, &queued));
+            let map = serenity::json::hashmap_to_json_map(edit.0);
+            if let Err(e) = ctx
+                .http
+                .edit_original_interaction_response(&token, &serenity::json::Value::from(map))
+                .await
+            {
+                tracing::warn!(guild_id = %guild_id, error = %e, "failed to report download progress");
+                break;
+            }
+            if done {
+                break;
+            }
+            // Discord rate limits edits, and nobody needs to see every one.
+            tokio::time::sleep(PROGRESS_INTERVAL).await;
         }
+    });
+}
+
+/// Updates the position of `queued` and how long until it plays, which will
+/// have changed if tracks ahead of it finished in the meantime.
+async fn update_place(ctx: &Context, guild_id: GuildId, queued: &mut QueuedTrack) -> Result<()> {
+    let queue = {
+        let handler_lock = current_call(ctx, guild_id).await?;
+        let handler = lock_call(&handler_lock).await?;
+        handler.queue().current_queue()
     };
+    if let Some(index) = queue
+        .iter()
+        .position(|track| track.uuid() == queued.handle.uuid())
+    {
+        let crossfade = crate::settings::guild_settings(guild_id).await?.crossfade();
+        queued.position = index + 1;
+        queued.wait = time_until_last(&queue[..=index], crossfade).await;
+    }
+    Ok(())
+}
 
-    crate::util::respond_to_interaction(interaction, &ctx.http, true, "Beginning to queue song")
-        .await;
+/// Queues `tracks` one after another for the user of `interaction`, keeping
+/// the deferred response up to date with how far along it is.
+///
+/// A track that fails to queue is skipped and listed at the end, rather than
+/// stopping the rest. So is a failed progress edit, since a long playlist can
+/// outlast the interaction token, in which case the summary goes to the
+/// channel instead.
+pub async fn play_saved(
+    ctx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    name: &str,
+    tracks: &[SavedTrack],
+) -> Result<()> {
+    let guild_id = guild_id(interaction)?;
+    // Problems that would fail every track should fail the command instead.
+    join_call(ctx, guild_id, interaction.user.id).await?;
 
-    let rain_enabled = match crate::util::RAIN_ENABLED.try_lock() {
-        Ok(map) => *map.get(&guild_id.to_string()).unwrap_or(&false),
-        Err(e) => {
-            eprintln!("Failed to lock RAIN_ENABLED map with error {}", e);
-            false
+    let mut failed = Vec::new();
+    let mut last_edit: Option<std::time::Instant> = None;
+    for (i, track) in tracks.iter().enumerate() {
+        // Discord rate limits edits, like in `report_progress`.
+        if last_edit.map_or(true, |at| at.elapsed() >= PROGRESS_INTERVAL) {
+            last_edit = Some(std::time::Instant::now());
+            let progress = format!(
+                "Queuing {} of {} from {}: {}",
+                i + 1,
+                tracks.len(),
+                name,
+                track.title
+            );
+            if let Err(e) = edit_response(interaction, &ctx.http, progress).await {
+                tracing::warn!(guild_id = %guild_id, error = %e, "failed to report playlist progress");
+            }
         }
-    };
-    let norain_source_path_str = format!("./queue/norain_{}.mp3", video_id);
-    let norain_source_path = std::path::Path::new(&norain_source_path_str);
-
-    if !norain_source_path.exists() {
-        // Make queue folder if it doesn't exist
-        let queue_folder = std::path::Path::new("queue");
-        if !queue_folder.exists() {
-            std::fs::create_dir(queue_folder).expect("Failed to create queue folder");
+        if let Err(e) = queue_source(
+            ctx,
+            guild_id,
+            interaction.user.id,
+            &track.source,
+            None,
+            Some(interaction.channel_id),
+        )
+        .await
+        {
+            tracing::warn!(
+                guild_id = %guild_id,
+                source = ?track.source,
+                error = %e,
+                "failed to queue saved track"
+            );
+            failed.push(format!("{}: {}", track.title, e.user_message()));
         }
+    }
 
-        let download_command = format!(
-            "yt-dlp -f 'ba' -x --audio-format mp3 \'{}\' -o \'{}\'",
-            url, norain_source_path_str
-        );
-        match std::process::Command::new("sh")
-            .arg("-c")
-            .arg(&download_command)
-            .output()
-        {
-            Ok(output) => {
-                if !output.status.success() {
-                    eprintln!(
-                        "Failed to download video: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    );
-                    crate::util::follow_up_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "Error downloading video/audio",
-                    )
-                    .await;
-
-                    return;
-                }
+    let mut message = format!(
+        "Queued {} of {} tracks from {}",
+        tracks.len() - failed.len(),
+        tracks.len(),
+        name
+    );
+    for failure in failed.iter().take(MAX_LISTED_FAILURES) {
+        message.push_str("\n- ");
+        message.push_str(failure);
+    }
+    if failed.len() > MAX_LISTED_FAILURES {
+        message.push_str(&format!(
+            "\n...and {} more",
+            failed.len() - MAX_LISTED_FAILURES
+        ));
+    }
+    if let Err(e) = edit_response(interaction, &ctx.http, &message).await {
+        tracing::warn!(guild_id = %guild_id, error = %e, "failed to edit playlist summary, sending it instead");
+        interaction.channel_id.say(&ctx.http, &message).await?;
+    }
+    Ok(())
+}
+
+/// Adds `source` to the queue of `guild_id`, joining the voice channel of
+/// `user_id` if the bot isn't in one yet.
+///
+/// Tracks that have to be downloaded or mixed are queued straight away and
+/// fetched in the background, in queue order. If that fails, the track is
+/// dropped from the queue and `notify` is told why. Progress up to queuing is
+/// edited into the deferred response of `interaction`, if there is one.
+pub async fn queue_source(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    source: &TrackSource,
+    interaction: Option<&ApplicationCommandInteraction>,
+    notify: Option<ChannelId>,
+) -> Result<QueuedTrack> {
+    let handler_lock = join_call(ctx, guild_id, user_id).await?;
+    let settings = crate::settings::guild_settings(guild_id).await?;
+    let rain_enabled = rain_enabled(guild_id).await?;
+
+    let request = match source {
+        TrackSource::YouTube { id } => {
+            crate::selfcheck::require(crate::selfcheck::Feature::YouTube)?;
+            if let Some(interaction) = interaction {
+                edit_response(interaction, &ctx.http, "Beginning to queue song").await?;
             }
-            Err(e) => {
-                eprintln!("failed to execute yt-dlp process: {:?}", e);
-                eprintln!("Command: {}", download_command);
-
-                crate::util::follow_up_interaction(
-                    interaction,
-                    &ctx.http,
-                    true,
-                    "Error downloading video/audio",
+
+            let url = format!("https://www.youtube.com/watch?v={}", id);
+            let cached = crate::track::cached_metadata(id).await;
+            let probed = match &cached {
+                Some(metadata) => metadata.clone(),
+                None => crate::track::fetch_metadata(&url).await?,
+            };
+            settings.check_limits(&probed)?;
+
+            if probed.is_live() {
+                // Live streams never finish downloading, so they are streamed
+                // straight into the call instead of going through the cache.
+                let stream = crate::live::LiveStream::youtube(
+                    &url,
+                    live_rain_path(rain_enabled)?,
+                    settings.effects.for_live(),
+                    &probed,
+                );
+                let input = Restartable::new(stream, true).await?;
+                return enqueue(
+                    ctx,
+                    &handler_lock,
+                    guild_id,
+                    user_id,
+                    input,
+                    probed,
+                    rain_enabled,
                 )
                 .await;
+            }
+            crate::prefetch::FetchRequest {
+                source: source.clone(),
+                metadata: probed,
+                indexed: cached.is_some(),
+                settings,
+                rain_enabled,
+            }
+        }
+        TrackSource::Local { path } => {
+            if crate::library::library_path().is_none() {
+                return Err(Error::LibraryDisabled);
+            }
+            let track = crate::library::get(path).await.ok_or(Error::NotInLibrary)?;
+            let mut metadata = track.metadata();
+            settings.check_limits(&metadata)?;
 
-                return;
+            metadata.loudness = crate::track::cached_metadata(&metadata.id)
+                .await
+                .and_then(|cached| cached.loudness);
+            crate::prefetch::FetchRequest {
+                source: source.clone(),
+                metadata,
+                indexed: true,
+                settings,
+                rain_enabled,
             }
         }
+        TrackSource::Radio { name, url } => {
+            let metadata = TrackMetadata::radio(name, url);
+            settings.check_limits(&metadata)?;
+            crate::radio::check_public(url).await?;
+            let stream = crate::live::LiveStream::direct(
+                url,
+                live_rain_path(rain_enabled)?,
+                settings.effects.for_live(),
+                &metadata,
+            );
+            let input = Restartable::new(stream, true).await?;
+            let queued = enqueue(
+                ctx,
+                &handler_lock,
+                guild_id,
+                user_id,
+                input,
+                metadata,
+                rain_enabled,
+            )
+            .await?;
+            crate::radio::watch_stream_title(queued.handle.clone(), url.clone());
+            return Ok(queued);
+        }
+    };
+
+    let metadata = request
+        .metadata
+        .clone()
+        .at_speed(request.settings.effects.speed.factor());
+    let (audio, fetch) = crate::prefetch::pending(request, &metadata);
+    // Here, we use lazy restartable sources to make sure that we don't pay
+    // for decoding, playback on tracks which aren't actually live yet.
+    let input = Restartable::new(audio, true).await?;
+
+    let mut queued = enqueue(
+        ctx,
+        &handler_lock,
+        guild_id,
+        user_id,
+        input,
+        metadata,
+        rain_enabled,
+    )
+    .await?;
+    queued.status = Some(fetch.subscribe());
+    crate::prefetch::submit(ctx, guild_id, fetch, queued.handle.clone(), notify).await;
+    Ok(queued)
+}
+
+/// Downloads and mixes the audio for `request`, which the prefetch worker
+/// does for each queued track in turn.
+///
+/// Returns the path of the file to play and the track's metadata, with its
+/// loudness measured.
+pub async fn fetch_audio(
+    request: &crate::prefetch::FetchRequest,
+    status: &tokio::sync::watch::Sender<crate::prefetch::Status>,
+) -> Result<(String, TrackMetadata)> {
+    let settings = &request.settings;
+    let (song_path, cache_id, metadata) = match &request.source {
+        TrackSource::YouTube { id } => {
+            status.send_replace(crate::prefetch::Status::Downloading(Default::default()));
+            let url = format!("https://www.youtube.com/watch?v={}", id);
+            let (song_path, metadata) = download_song(
+                &url,
+                settings,
+                request.metadata.clone(),
+                request.indexed,
+                &|progress| {
+                    status.send_replace(crate::prefetch::Status::Downloading(progress));
+                },
+            )
+            .await?;
+            (song_path, id.clone(), metadata)
+        }
+        TrackSource::Local { path } => {
+            let track = crate::library::get(path).await.ok_or(Error::NotInLibrary)?;
+            (
+                track.path.to_string_lossy().to_string(),
+                track.cache_id(),
+                request.metadata.clone(),
+            )
+        }
+        TrackSource::Radio { .. } => {
+            return Err(Error::DownloadFailed(
+                "radio streams are played live".to_string(),
+            ))
+        }
+    };
+
+    let mut metadata = metadata;
+    if metadata.loudness.is_none() {
+        status.send_replace(crate::prefetch::Status::Measuring);
+    }
+    measure_loudness(&mut metadata, &song_path).await;
+    let audio_path = prepare_audio(
+        song_path,
+        &cache_id,
+        metadata.loudness,
+        metadata.duration(),
+        request.rain_enabled,
+        settings,
+        &|progress| {
+            status.send_replace(crate::prefetch::Status::Encoding(progress));
+        },
+    )
+    .await?;
+    Ok((audio_path, metadata))
+}
+
+/// Whether [`fetch_audio`] leaves the audio for `request` cached as Ogg Opus,
+/// which has to be known before it is done.
+pub fn fetches_opus(request: &crate::prefetch::FetchRequest) -> bool {
+    if request.rain_enabled || !request.settings.effects.is_empty() {
+        return true;
+    }
+    match &request.source {
+        TrackSource::YouTube { id } => !std::path::Path::new(&legacy_download_path(id)).exists(),
+        TrackSource::Local { .. } | TrackSource::Radio { .. } => false,
+    }
+}
+
+/// Measures the loudness of a cached track, unless that was done before, and
+/// records it in the metadata index.
+///
+/// A track that can't be measured just plays without normalization.
+async fn measure_loudness(metadata: &mut TrackMetadata, song_path: &str) {
+    if metadata.loudness.is_some() {
+        return;
     }
+    match crate::loudness::analyze(song_path).await {
+        Ok(loudness) => {
+            metadata.loudness = Some(loudness);
+            crate::track::store_metadata(metadata).await;
+        }
+        Err(e) => {
+            tracing::warn!(id = %metadata.id, error = %e, "failed to measure loudness");
+        }
+    }
+}
 
-    let audio_source = if rain_enabled {
-        let rain_source_path_str = format!("./queue/{}.mp3", video_id);
-        let rain_source_path = std::path::Path::new(&rain_source_path_str);
+/// The rain to loop under a live stream, which is mixed as it plays rather
+/// than cached.
+fn live_rain_path(rain_enabled: bool) -> Result<Option<String>> {
+    if rain_enabled {
+        Ok(Some(rain_path()?))
+    } else {
+        Ok(None)
+    }
+}
 
-        if !rain_source_path.exists() {
-            crate::util::follow_up_interaction(interaction, &ctx.http, true, "Encoding song").await;
+/// Gets the call in `guild_id`, joining the voice channel of `user_id` if the
+/// bot isn't in one yet.
+pub async fn join_call(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<Arc<Mutex<Call>>> {
+    let manager = songbird_manager(ctx).await;
+    if let Some(handler_lock) = manager.get(guild_id) {
+        return Ok(handler_lock);
+    }
 
-            // Download/mix the video/audio into a single source.
+    let connect_to = user_voice_channel(ctx, guild_id, user_id)?;
+    let (handler_lock, success) = manager.join(guild_id, connect_to).await;
+    success.map_err(Error::JoinFailed)?;
+    Ok(handler_lock)
+}
 
-            let rain_path = match std::env::var("RAIN_PATH") {
-                Ok(path) => path,
-                Err(e) => {
-                    eprintln!("Failed to get RAIN_PATH: {}", e);
-                    crate::util::follow_up_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "Internal Error... Please try again later",
-                    )
-                    .await;
+/// Adds `source` to the end of the queue, with `metadata` attached, its play
+/// recorded in the history once it starts and autoplay taking over once it
+/// ends.
+async fn enqueue(
+    ctx: &Context,
+    handler_lock: &Arc<Mutex<Call>>,
+    guild_id: GuildId,
+    requester: UserId,
+    source: Restartable,
+    metadata: TrackMetadata,
+    rain_enabled: bool,
+) -> Result<QueuedTrack> {
+    let volume = crate::loudness::track_volume(metadata.loudness, rain_enabled);
 
-                    return;
-                }
-            };
-            let mix_command = format!(
-                    "ffmpeg -stream_loop -1 -i \"{}\" -i \"{}\"  -filter_complex \"[0:a]volume=0.75[a0];[1:a]volume=1[a1];[a0][a1]amerge[a]\" -map \"[a]\" -ac 2 \"{}\"",
-                    rain_path, norain_source_path_str, rain_source_path_str
-                );
-            match std::process::Command::new("sh")
-                .arg("-c")
-                .arg(&mix_command)
-                .output()
-            {
-                Ok(output) => {
-                    if !output.status.success() {
-                        eprintln!(
-                            "Failed to mix audio: {}",
-                            String::from_utf8_lossy(&output.stderr)
-                        );
-                        crate::util::follow_up_interaction(
-                            interaction,
-                            &ctx.http,
-                            true,
-                            "Error mixing audio",
-                        )
-                        .await;
-
-                        return;
+    let (track, handle) = songbird::create_player(source.into());
+    {
+        let mut typemap = handle.typemap().write().await;
+        typemap.insert::<crate::track::TrackMetadataKey>(Arc::new(metadata.clone()));
+        typemap.insert::<crate::loudness::VolumeKey>(volume);
+    }
+    if let Err(e) = handle.set_volume(volume) {
+        tracing::warn!(guild_id = %guild_id, error = %e, "failed to set track volume");
+    }
+    // Added before the track is queued, so that an empty queue starting it
+    // straight away can't beat the handler to it.
+    let recorder = crate::history::RecordPlay {
+        guild_id,
+        track: SavedTrack::from(&metadata),
+        requester,
+        rain_enabled,
+    };
+    if let Err(e) = handle.add_event(Event::Track(TrackEvent::Play), recorder) {
+        tracing::warn!(guild_id = %guild_id, error = %e, "failed to watch track for history");
+    }
+    let autoplay = crate::autoplay::Autoplay {
+        ctx: ctx.clone(),
+        guild_id,
+        requester,
+        track: SavedTrack::from(&metadata),
+    };
+    if let Err(e) = handle.add_event(Event::Track(TrackEvent::End), autoplay) {
+        tracing::warn!(guild_id = %guild_id, error = %e, "failed to watch track for autoplay");
+    }
+
+    let crossfade = crate::settings::guild_settings(guild_id).await?.crossfade();
+    if let Some(duration) = metadata.duration().filter(|_| !crossfade.is_zero()) {
+        let fade = crate::crossfade::fade_length(crossfade, duration);
+        let handler = crate::crossfade::Crossfade {
+            ctx: ctx.clone(),
+            guild_id,
+            fade,
+        };
+        if let Err(e) = handle.add_event(Event::Delayed(duration - fade), handler) {
+            tracing::warn!(guild_id = %guild_id, error = %e, "failed to set up crossfade");
+        }
+    }
+
+    let queue = {
+        let mut handler = lock_call(handler_lock).await?;
+        handler.enqueue(track);
+        handler.queue().current_queue()
+    };
+
+    let wait = time_until_last(&queue, crossfade).await;
+    Ok(QueuedTrack {
+        handle,
+        metadata,
+        position: queue.len(),
+        wait,
+        rain_enabled,
+        status: None,
+    })
+}
+
+/// Fills in the confirmation embed for a track `user` queued.
+pub fn queued_embed<'a>(
+    embed: &'a mut CreateEmbed,
+    user: &User,
+    queued: &QueuedTrack,
+) -> &'a mut CreateEmbed {
+    let metadata = &queued.metadata;
+    embed
+        .author(|author| {
+            author
+                .name(format!("Queued by {}", user.tag()))
+                .icon_url(user.face())
+        })
+        .title(&metadata.title)
+        .field(
+            "Channel",
+            metadata.channel_name().unwrap_or("Unknown"),
+            true,
+        )
+        .field(
+            "Duration",
+            match metadata.duration() {
+                _ if metadata.is_live() => "Live".to_string(),
+                Some(duration) => crate::track::format_duration(duration),
+                None => "Unknown".to_string(),
+            },
+            true,
+        )
+        .field("Position", queued.position, true)
+        .field(
+            "Plays in",
+            match queued.wait {
+                Some(wait) if wait.is_zero() => "Now".to_string(),
+                Some(wait) => crate::track::format_duration(wait),
+                None => "Unknown".to_string(),
+            },
+            true,
+        )
+        .field("Rain", if queued.rain_enabled { "On" } else { "Off" }, true);
+    if let Some(status) = &queued.status {
+        embed.field("Audio", status.borrow().describe(), true);
+    }
+    if let Some(url) = &metadata.webpage_url {
+        embed.url(url);
+    }
+    if let Some(thumbnail) = &metadata.thumbnail {
+        embed.thumbnail(thumbnail);
+    }
+    embed
+}
+
+/// Downloads `url` into the cache.
+///
+/// Returns the path of the downloaded audio and the track's metadata.
+/// `indexed` says whether `probed` came from the metadata index. How the
+/// download is going is passed to `on_progress`.
+async fn download_song(
+    url: &str,
+    settings: &crate::settings::GuildSettings,
+    probed: TrackMetadata,
+    indexed: bool,
+    on_progress: &(dyn Fn(crate::progress::Progress) + Sync),
+) -> Result<(String, TrackMetadata)> {
+    let legacy_path_str = legacy_download_path(&probed.id);
+    if std::path::Path::new(&legacy_path_str).exists() {
+        // Downloaded before the cache switched to Opus.
+        if !indexed {
+            crate::track::store_metadata(&probed).await;
+        }
+        return Ok((legacy_path_str, probed));
+    }
+
+    let norain_source_path_str = format!("{}/norain_{}.opus", crate::cache::QUEUE_DIR, probed.id);
+    let norain_source_path = std::path::Path::new(&norain_source_path_str);
+
+    let metadata = if !norain_source_path.exists() {
+        // Make queue folder if it doesn't exist
+        let queue_folder = std::path::Path::new("queue");
+        if !queue_folder.exists() {
+            std::fs::create_dir(queue_folder).map_err(|e| {
+                Error::DownloadFailed(format!("failed to create queue folder: {}", e))
+            })?;
+        }
+
+        let id = probed.id.as_str();
+        let output = crate::ytdlp::with_retries(url, |format| async move {
+            let max_filesize = format!("{}M", settings.max_filesize_mb);
+            let output_template = format!("{}/norain_{}.%(ext)s", crate::cache::QUEUE_DIR, id);
+            let output = crate::progress::run(
+                // --dump-json --no-simulate prints the metadata while still downloading.
+                // YouTube's best audio is nearly always Opus already, which yt-dlp
+                // then only remuxes into Ogg; anything else gets converted.
+                // --progress brings back the progress --dump-json turns off.
+                crate::ytdlp::command().args([
+                    "-f",
+                    format,
+                    "-x",
+                    "--audio-format",
+                    "opus",
+                    "--dump-json",
+                    "--no-simulate",
+                    "--progress",
+                    "--newline",
+                    "--progress-template",
+                    crate::progress::YTDLP_TEMPLATE,
+                    "--max-filesize",
+                    max_filesize.as_str(),
+                    "-o",
+                    output_template.as_str(),
+                    url,
+                ]),
+                |line| match crate::progress::parse_ytdlp(line) {
+                    Some(progress) => {
+                        on_progress(progress);
+                        true
                     }
-                }
-                Err(e) => {
-                    eprintln!("failed to spawn ffmpeg to mix audio with rain: {}", e);
-                    eprintln!("Command: {}", mix_command);
-
-                    crate::util::follow_up_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "Error mixing audio",
-                    )
-                    .await;
-
-                    return;
-                }
+                    None => false,
+                },
+            )
+            .await
+            .map_err(|e| {
+                Error::DownloadFailed(format!("failed to execute yt-dlp process: {:?}", e))
+            })?;
+            if !output.status.success() {
+                return Err(crate::ytdlp::failure(&output));
             }
+            Ok(output)
+        })
+        .await?;
+
+        if !norain_source_path.exists() {
+            // yt-dlp skips the download instead of failing when it goes over
+            // --max-filesize.
+            return Err(Error::TrackRejected(format!(
+                "This track is over this server's limit of {} MB",
+                settings.max_filesize_mb
+            )));
         }
-        rain_source_path_str
+
+        let metadata = crate::track::parse_metadata(&output.stdout)?;
+        crate::track::store_metadata(&metadata).await;
+        metadata
     } else {
-        norain_source_path_str
+        if !indexed {
+            // Downloaded before metadata was being recorded.
+            crate::track::store_metadata(&probed).await;
+        }
+        probed
     };
 
-    // Here, we use lazy restartable sources to make sure that we don't pay
-    // for decoding, playback on tracks which aren't actually live yet.
-    let source = match Restartable::ffmpeg(audio_source, true).await {
-        Ok(source) => source,
-        Err(why) => {
-            eprintln!("Err starting source: {:?}", why);
-            crate::util::follow_up_interaction(
-                interaction,
-                &ctx.http,
-                true,
-                "Error sourcing ffmpeg",
-            )
-            .await;
+    Ok((norain_source_path_str, metadata))
+}
 
-            return;
-        }
+/// Where tracks were downloaded to before the cache switched to Opus.
+fn legacy_download_path(id: &str) -> String {
+    format!("{}/norain_{}.mp3", crate::cache::QUEUE_DIR, id)
+}
+
+/// Mixes the rain and effects into `song_path` if there are any, caching the
+/// result under `cache_id` and everything that went into the mix.
+///
+/// With rain on, the track is brought to the target loudness as part of the
+/// mix, with the rain at its own level under it.
+///
+/// Returns the path of the file to play. How the encode is going is passed to
+/// `on_progress`.
+async fn prepare_audio(
+    song_path: String,
+    cache_id: &str,
+    loudness: Option<crate::loudness::Loudness>,
+    duration: Option<std::time::Duration>,
+    rain_enabled: bool,
+    settings: &crate::settings::GuildSettings,
+    on_progress: &(dyn Fn(crate::progress::Progress) + Sync),
+) -> Result<String> {
+    let effects = &settings.effects;
+    let effects_filter = effects.filter();
+    if !rain_enabled && effects_filter.is_none() {
+        return Ok(song_path);
+    }
+    // How long the song lasts once its effects are applied.
+    let song_duration = duration.map(|duration| duration.div_f64(effects.speed.factor()));
+
+    // Everything but the output, which is named after a hash of the rest.
+    let (mut mix_args, rain_fingerprint) = if rain_enabled {
+        // Download/mix the video/audio into a single source.
+        let rain_path = rain_path()?;
+        let rain_gain = crate::loudness::rain_gain_db(&rain_path).await;
+        let song_gain = loudness
+            .map(|loudness| loudness.gain_db(crate::loudness::TARGET_LUFS))
+            .unwrap_or(0.0);
+        (
+            crate::mix::rain_mix_args(
+                &song_path,
+                &rain_path,
+                song_gain,
+                rain_gain,
+                effects_filter.as_deref(),
+                song_duration
+                    .filter(|_| settings.fades_rain())
+                    .map(|duration| duration.as_secs_f64()),
+            ),
+            // The rain can be swapped out under the same path.
+            crate::cache::file_fingerprint(&rain_path)?,
+        )
+    } else {
+        (
+            crate::mix::effects_args(&song_path, &effects_filter.unwrap_or_default()),
+            String::new(),
+        )
     };
+    mix_args.extend(crate::mix::OUTPUT_ARGS.iter().map(|arg| arg.to_string()));
+    let mixed_path_str = crate::cache::mix_path(cache_id, &rain_fingerprint, &mix_args.join(" "));
+    let mixed_path = std::path::Path::new(&mixed_path_str);
 
-    let queue_len = {
-        let mut handler =
-            match timeout(std::time::Duration::from_secs(5), handler_lock.lock()).await {
-                Ok(handler) => handler,
-                Err(e) => {
-                    eprintln!("Failed to lock handler with error {}", e);
-                    crate::util::follow_up_interaction(
-                        interaction,
-                        &ctx.http,
-                        true,
-                        "There was an error adding the song to the queue. Please try again later.",
-                    )
-                    .await;
-
-                    return;
+    if !mixed_path.exists() {
+        // Encoded next to the mix and renamed into place once it's complete,
+        // so a crash or a concurrent mix of the same track never leaves a
+        // partial file under the final name.
+        let temp_path = format!("{}.{:08x}.part", mixed_path_str, rand::random::<u32>());
+        on_progress(Default::default());
+        let mut progress = crate::progress::FfmpegProgress::new(song_duration);
+        let output = crate::progress::run(
+            tokio::process::Command::new("ffmpeg")
+                .args(["-nostats", "-progress", "pipe:1"])
+                .args(&mix_args)
+                .args(["-f", "ogg"])
+                .arg(&temp_path),
+            |line| {
+                if let Some(update) = progress.parse(line) {
+                    on_progress(update);
                 }
-            };
-        handler.enqueue_source(source.into());
-        handler.queue().len()
-    };
+                true
+            },
+        )
+        .await
+        .map_err(|e| {
+            Error::MixFailed(format!(
+                "failed to spawn ffmpeg to mix audio: {} (arguments: {:?})",
+                e, mix_args
+            ))
+        })?;
+        if !output.status.success() {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(Error::MixFailed(
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+        if let Err(e) = std::fs::rename(&temp_path, mixed_path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(Error::MixFailed(format!(
+                "failed to move the mix into place at {}: {}",
+                mixed_path_str, e
+            )));
+        }
+        if rain_enabled {
+            crate::cache::remove_stale_mixes(cache_id, &rain_fingerprint);
+        }
+    }
 
-    crate::util::follow_up_interaction(
-        interaction,
-        &ctx.http,
-        false,
-        format!(
-            "User {} added song {} to queue: position {} (rain enabled: {})",
-            interaction.user.tag(),
-            url,
-            queue_len,
-            rain_enabled
-        ),
-    )
-    .await;
+    Ok(mixed_path_str)
+}
+
+/// How long until the last track in `queue` starts playing, or `None` if the
+/// length of a track ahead of it is unknown.
+///
+/// Each track ahead of it is cut short by `crossfade`, since the next one
+/// starts while it fades out.
+async fn time_until_last(
+    queue: &[TrackHandle],
+    crossfade: std::time::Duration,
+) -> Option<std::time::Duration> {
+    let (_, ahead) = queue.split_last()?;
+    let mut wait = std::time::Duration::ZERO;
+    for (i, track) in ahead.iter().enumerate() {
+        let duration = track.metadata().duration?;
+        let duration = duration - crate::crossfade::fade_length(crossfade, duration);
+        if i == 0 {
+            let position = track.get_info().await.ok()?.position;
+            wait += duration.saturating_sub(position);
+        } else {
+            wait += duration;
+        }
+    }
+    Some(wait)
 }
@@ -0,0 +1,435 @@
+use crate::error::{Error, Result};
+use lazy_static::lazy_static;
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::Output;
+use std::time::Duration;
+
+/// Formats asked for in turn, moving on when YouTube doesn't offer one. The
+/// last is the best format with video, which only the audio is kept from.
+const FORMATS: &[&str] = &["ba[acodec=opus]/ba", "b"];
+
+/// How many times yt-dlp is run before a failure that may be temporary is
+/// given up on.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// How long to wait before the first retry, doubling with each one after.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Proxy schemes yt-dlp understands.
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+lazy_static! {
+    /// Checked by [`validate`] at startup, so it can't fail to parse here.
+    static ref CONFIG: Config = Config::from_env().unwrap_or_default();
+}
+
+/// How yt-dlp is run, from the environment.
+#[derive(Debug)]
+pub struct Config {
+    /// The binary to run, `YTDLP_PATH`, or `yt-dlp` from the `PATH`.
+    pub binary: String,
+    /// A Netscape format cookies file, `YTDLP_COOKIES`, which gets past age
+    /// restrictions and bot checks.
+    pub cookies: Option<PathBuf>,
+    /// A proxy URL, `YTDLP_PROXY`.
+    pub proxy: Option<String>,
+    /// Arguments added to every run, `YTDLP_EXTRA_ARGS`, split on whitespace.
+    pub extra_args: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            binary: "yt-dlp".to_string(),
+            cookies: None,
+            proxy: None,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    fn from_env() -> Result<Self> {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+
+        let proxy = var("YTDLP_PROXY");
+        if let Some(proxy) = &proxy {
+            let scheme = proxy.split_once("://").map(|(scheme, _)| scheme);
+            if !scheme.map_or(false, |scheme| PROXY_SCHEMES.contains(&scheme)) {
+                return Err(Error::Config(format!(
+                    "YTDLP_PROXY must be a URL starting with one of {}://, got {}",
+                    PROXY_SCHEMES.join("://, "),
+                    proxy
+                )));
+            }
+        }
+
+        Ok(Self {
+            binary: var("YTDLP_PATH").unwrap_or_else(|| "yt-dlp".to_string()),
+            cookies: var("YTDLP_COOKIES").map(PathBuf::from),
+            proxy,
+            extra_args: var("YTDLP_EXTRA_ARGS")
+                .map(|args| args.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The arguments every run starts with.
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(cookies) = &self.cookies {
+            args.push("--cookies".to_string());
+            args.push(cookies.to_string_lossy().to_string());
+        }
+        if let Some(proxy) = &self.proxy {
+            args.push("--proxy".to_string());
+            args.push(proxy.clone());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
+
+/// Checks the yt-dlp configuration.
+///
+/// Meant to be called at startup, so that a typo in the environment stops the
+/// bot there instead of failing every download.
+pub fn validate() -> Result<()> {
+    let config = Config::from_env()?;
+
+    if let Some(cookies) = &config.cookies {
+        let contents = std::fs::read_to_string(cookies).map_err(|e| {
+            Error::Config(format!(
+                "failed to read YTDLP_COOKIES file {}: {}",
+                cookies.display(),
+                e
+            ))
+        })?;
+        // yt-dlp refuses cookie files without the header.
+        if !contents.starts_with("# Netscape HTTP Cookie File")
+            && !contents.starts_with("# HTTP Cookie File")
+        {
+            return Err(Error::Config(format!(
+                "YTDLP_COOKIES file {} is not in Netscape format",
+                cookies.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The version of the configured yt-dlp, which fails if it can't be run.
+pub fn version() -> Result<String> {
+    let output = blocking_command()
+        .arg("--version")
+        .output()
+        .map_err(|e| Error::Config(format!("failed to run yt-dlp at {}: {}", CONFIG.binary, e)))?;
+    if !output.status.success() {
+        return Err(Error::Config(format!(
+            "{} --version failed: {}",
+            CONFIG.binary,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// A yt-dlp command with the configured binary and options.
+pub fn command() -> tokio::process::Command {
+    let mut command = tokio::process::Command::new(&CONFIG.binary);
+    command.args(CONFIG.args());
+    command
+}
+
+/// Like [`command`], for processes that are handed over to songbird.
+pub fn blocking_command() -> std::process::Command {
+    let mut command = std::process::Command::new(&CONFIG.binary);
+    command.args(CONFIG.args());
+    command
+}
+
+/// Why yt-dlp couldn't get a video, as far as can be told from what it
+/// printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    AgeRestricted,
+    Private,
+    RegionBlocked,
+    Removed,
+    RateLimited,
+    /// YouTube wants a signed in session before it serves anything, which
+    /// waiting won't change.
+    BotCheck,
+    FormatUnavailable,
+    Other,
+}
+
+impl Failure {
+    /// Sorts out a failure from yt-dlp's stderr.
+    pub fn classify(stderr: &str) -> Self {
+        let stderr = stderr.to_lowercase();
+        let mentions = |phrases: &[&str]| phrases.iter().any(|phrase| stderr.contains(phrase));
+
+        // "Video unavailable" prefixes most of the others, so it goes last.
+        if mentions(&[
+            "confirm your age",
+            "age-restricted",
+            "inappropriate for some users",
+        ]) {
+            Failure::AgeRestricted
+        } else if mentions(&["private video", "video is private"]) {
+            Failure::Private
+        } else if mentions(&[
+            "in your country",
+            "geo restriction",
+            "geo-restricted",
+            "not available from your location",
+        ]) {
+            Failure::RegionBlocked
+        } else if mentions(&["not a bot"]) {
+            Failure::BotCheck
+        } else if mentions(&["http error 429", "too many requests"]) {
+            Failure::RateLimited
+        } else if mentions(&["requested format is not available"]) {
+            Failure::FormatUnavailable
+        } else if mentions(&[
+            "video unavailable",
+            "this video has been removed",
+            "this video is no longer available",
+            "account associated with this video has been terminated",
+        ]) {
+            Failure::Removed
+        } else {
+            Failure::Other
+        }
+    }
+
+    /// Whether the video itself can't be played, as opposed to something
+    /// going wrong on our end.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            Failure::AgeRestricted | Failure::Private | Failure::RegionBlocked | Failure::Removed
+        )
+    }
+
+    /// Whether trying again later might work.
+    fn is_retryable(&self) -> bool {
+        matches!(self, Failure::RateLimited | Failure::Other)
+    }
+
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            Failure::AgeRestricted => "This video is age-restricted, so I can't play it",
+            Failure::Private => "This video is private",
+            Failure::RegionBlocked => "This video isn't available in my region",
+            Failure::Removed => "This video has been removed or doesn't exist",
+            Failure::RateLimited => {
+                "YouTube is limiting my downloads right now. Please try again in a few minutes"
+            }
+            Failure::BotCheck => {
+                "YouTube is asking me to sign in before it lets me download. The bot owner needs to set YTDLP_COOKIES"
+            }
+            Failure::FormatUnavailable => "YouTube doesn't offer this video in a format I can play",
+            Failure::Other => "Error downloading video/audio",
+        }
+    }
+}
+
+/// The error for a yt-dlp run that exited with `output`.
+pub fn failure(output: &Output) -> Error {
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    Error::YtDlp(Failure::classify(&stderr), stderr)
+}
+
+/// Runs yt-dlp for `url` through `run`, which is given the format to ask for.
+///
+/// Failures that may be temporary are retried with backoff, and a format
+/// YouTube doesn't offer falls back to the next one.
+pub async fn with_retries<T, F, Fut>(url: &str, mut run: F) -> Result<T>
+where
+    F: FnMut(&'static str) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut formats = FORMATS.iter().copied();
+    let mut format = formats.next().unwrap_or_default();
+    let mut attempt = 1;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let e = match run(format).await {
+            Ok(result) => return Ok(result),
+            Err(e) => e,
+        };
+        match &e {
+            Error::YtDlp(Failure::FormatUnavailable, _) => match formats.next() {
+                Some(next) => {
+                    tracing::info!(url, format = next, "falling back to another format");
+                    format = next;
+                }
+                None => return Err(e),
+            },
+            Error::YtDlp(failure, _) if failure.is_retryable() && attempt < MAX_ATTEMPTS => {
+                tracing::warn!(url, attempt, error = %e, "yt-dlp failed, retrying in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+                backoff *= 2;
+            }
+            _ => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What yt-dlp prints for each kind of failure, and errors that have
+    /// nothing to do with the video.
+    const SAMPLES: &[(&str, Failure)] = &[
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm your age. This video may be inappropriate for some users. Use --cookies-from-browser or --cookies for the authentication.",
+            Failure::AgeRestricted,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video",
+            Failure::Private,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. The uploader has not made this video available in your country",
+            Failure::RegionBlocked,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader",
+            Failure::Removed,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video is no longer available because the YouTube account associated with this video has been terminated.",
+            Failure::Removed,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable",
+            Failure::Removed,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm you\u{2019}re not a bot. Use --cookies-from-browser or --cookies for the authentication.",
+            Failure::BotCheck,
+        ),
+        (
+            "ERROR: unable to download video data: HTTP Error 429: Too Many Requests",
+            Failure::RateLimited,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Requested format is not available. Use --list-formats for a list of available formats",
+            Failure::FormatUnavailable,
+        ),
+        (
+            "ERROR: [Errno 2] No such file or directory: '/data/cookies.txt'\nERROR: cookie file /data/cookies.txt does not exist",
+            Failure::Other,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Unable to download API page: <urlopen error [Errno -3] Temporary failure in name resolution>",
+            Failure::Other,
+        ),
+    ];
+
+    #[test]
+    fn classifies_ytdlp_errors() {
+        for (stderr, expected) in SAMPLES {
+            assert_eq!(Failure::classify(stderr), *expected, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn only_problems_with_the_video_are_the_users() {
+        assert!(Failure::Removed.is_unavailable());
+        assert!(Failure::AgeRestricted.is_unavailable());
+        assert!(!Failure::RateLimited.is_unavailable());
+        assert!(!Failure::Other.is_unavailable());
+        assert!(Failure::RateLimited.is_retryable());
+        assert!(!Failure::Private.is_retryable());
+        assert!(!Failure::BotCheck.is_retryable());
+        assert!(!Failure::FormatUnavailable.is_retryable());
+    }
+
+    fn fails_with(failure: Failure) -> Error {
+        Error::YtDlp(failure, String::new())
+    }
+
+    #[tokio::test]
+    async fn falls_back_through_the_formats_in_order() {
+        let mut asked = Vec::new();
+        let result = with_retries("url", |format| {
+            asked.push(format);
+            let outcome = if asked.len() < FORMATS.len() {
+                Err(fails_with(Failure::FormatUnavailable))
+            } else {
+                Ok(format)
+            };
+            async move { outcome }
+        })
+        .await;
+        assert_eq!(asked, FORMATS);
+        assert_eq!(result.ok(), FORMATS.last().copied());
+    }
+
+    #[tokio::test]
+    async fn gives_up_once_every_format_is_unavailable() {
+        let mut asked = Vec::new();
+        let result: Result<()> = with_retries("url", |format| {
+            asked.push(format);
+            async { Err(fails_with(Failure::FormatUnavailable)) }
+        })
+        .await;
+        assert_eq!(asked, FORMATS);
+        assert!(matches!(
+            result,
+            Err(Error::YtDlp(Failure::FormatUnavailable, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_an_unavailable_video() {
+        let mut attempts = 0;
+        let result: Result<()> = with_retries("url", |_| {
+            attempts += 1;
+            async { Err(fails_with(Failure::Private)) }
+        })
+        .await;
+        assert_eq!(attempts, 1);
+        assert!(matches!(result, Err(Error::YtDlp(Failure::Private, _))));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_bot_check() {
+        let mut attempts = 0;
+        let result: Result<()> = with_retries("url", |_| {
+            attempts += 1;
+            async { Err(fails_with(Failure::BotCheck)) }
+        })
+        .await;
+        assert_eq!(attempts, 1);
+        assert!(matches!(result, Err(Error::YtDlp(Failure::BotCheck, _))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_a_rate_limit_with_the_same_format() {
+        let mut asked = Vec::new();
+        let result = with_retries("url", |format| {
+            asked.push(format);
+            let outcome = if asked.len() == 1 {
+                Err(fails_with(Failure::RateLimited))
+            } else {
+                Ok(())
+            };
+            async move { outcome }
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(asked, [FORMATS[0], FORMATS[0]]);
+    }
+}